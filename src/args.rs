@@ -0,0 +1,289 @@
+
+use std::path::PathBuf;
+
+use crate::conflict::Policy;
+use crate::transfer::{Mode, Verify};
+
+/// Command line help.
+pub const USAGE: &str = "\
+Usage: tv-rename [options] [directory]
+       tv-rename undo [options] [journal]
+       tv-rename apply [options] <plan.json>
+
+Options:
+  --show NAME           Show name, instead of guessing.
+  --season NUMBER       Season number, instead of guessing.
+  --episodes-per-season COUNTS
+                        Split anime numbering into seasons, like 12,13,24.
+  -r, --recursive       Every folder inside, like a whole show.
+  -y, --yes             Don't ask anything, fail if unsure.
+  --preset NAME         Naming preset: plex, jellyfin, kodi, emby.
+  --library PATH        Move into a library folder.
+  --mode MODE           rename, copy, hardlink, symlink, relative-symlink.
+  --verify              Check copies byte for byte.
+  --on-conflict POLICY  abort, skip, suffix, larger.
+  --dry-run             Show what would happen, change nothing.
+  --format FORMAT       Dry run output: text, json.
+  -h, --help            Show this help.";
+
+/// What to do.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Rename,
+    /// Undo the last run, or a chosen journal.
+    Undo(Option<String>),
+    /// Run a plan from a dry run.
+    Apply(PathBuf),
+    Help,
+}
+
+/// How to print a dry run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    /// Only the plan, for other tools.
+    Json,
+}
+
+/// Command line arguments.
+#[derive(Debug)]
+pub struct Args {
+    pub command: Command,
+    pub path: Option<PathBuf>,
+    pub show_name: Option<String>,
+    pub season: Option<u32>,
+    /// How many episodes are in each season, for absolute numbering.
+    pub episodes_per_season: Vec<u32>,
+    pub yes: bool,
+    pub recursive: bool,
+    pub preset: Option<String>,
+    pub library: Option<PathBuf>,
+    pub mode: Mode,
+    pub verify: Verify,
+    pub policy: Policy,
+    pub dry_run: bool,
+    pub format: Format,
+}
+
+impl Args {
+    /// Parse arguments, not including the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+        let mut parsed = Args {
+            command: Command::Rename,
+            path: None,
+            show_name: None,
+            season: None,
+            episodes_per_season: Vec::new(),
+            yes: false,
+            recursive: false,
+            preset: None,
+            library: None,
+            mode: Mode::Rename,
+            verify: Verify::Size,
+            policy: Policy::Abort,
+            dry_run: false,
+            format: Format::Text,
+        };
+        
+        let mut positional = Vec::new();
+        let mut args = args.into_iter();
+        
+        while let Some(arg) = args.next() {
+            match arg.as_ref() {
+                "-h" | "--help" => {
+                    parsed.command = Command::Help;
+                },
+                "-y" | "--yes" => {
+                    parsed.yes = true;
+                },
+                "--dry-run" => {
+                    parsed.dry_run = true;
+                },
+                "--format" => {
+                    let format = value(&arg, args.next())?;
+                    parsed.format = match format.as_ref() {
+                        "text" => Format::Text,
+                        "json" => Format::Json,
+                        _ => return Err(format!("Unknown format '{}', try: text, json", format)),
+                    };
+                },
+                "-r" | "--recursive" => {
+                    parsed.recursive = true;
+                },
+                "--verify" => {
+                    parsed.verify = Verify::Contents;
+                },
+                "--show" => {
+                    parsed.show_name = Some(value(&arg, args.next())?);
+                },
+                "--season" => {
+                    let season = value(&arg, args.next())?;
+                    parsed.season = Some(season.parse::<u32>()
+                        .map_err(|_| format!("Season '{}' is not a number.", season))?);
+                },
+                "--episodes-per-season" => {
+                    let counts = value(&arg, args.next())?;
+                    parsed.episodes_per_season = counts.split(',')
+                        .map(|count| count.trim().parse::<u32>()
+                            .ok()
+                            .filter(|count| *count > 0)
+                            .ok_or(format!("Bad episode count '{}'.", count)))
+                        .collect::<Result<Vec<u32>, String>>()?;
+                },
+                "--preset" => {
+                    parsed.preset = Some(value(&arg, args.next())?);
+                },
+                "--library" => {
+                    parsed.library = Some(PathBuf::from(value(&arg, args.next())?));
+                },
+                "--mode" => {
+                    let mode = value(&arg, args.next())?;
+                    parsed.mode = Mode::parse(&mode)
+                        .ok_or(format!("Unknown mode '{}', try: rename, copy, hardlink, symlink, relative-symlink", mode))?;
+                },
+                "--on-conflict" => {
+                    let policy = value(&arg, args.next())?;
+                    parsed.policy = Policy::parse(&policy)
+                        .ok_or(format!("Unknown conflict policy '{}', try: abort, skip, suffix, larger", policy))?;
+                },
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    return Err(format!("Unknown option '{}'.", arg));
+                },
+                _ => positional.push(arg),
+            }
+        }
+        
+        let mut positional = positional.into_iter();
+        
+        match positional.next() {
+            Some(ref first) if first == "undo" => {
+                let journal = positional.next();
+                
+                if parsed.command != Command::Help {
+                    parsed.command = Command::Undo(journal);
+                }
+            },
+            Some(ref first) if first == "apply" => {
+                let plan = positional.next()
+                    .ok_or(String::from("Apply needs a plan file."))?;
+                
+                if parsed.command != Command::Help {
+                    parsed.command = Command::Apply(PathBuf::from(plan));
+                }
+            },
+            Some(path) => {
+                parsed.path = Some(PathBuf::from(path));
+            },
+            None => (),
+        }
+        
+        if let Some(extra) = positional.next() {
+            return Err(format!("Unexpected argument '{}'.", extra));
+        }
+        
+        if parsed.format == Format::Json && !parsed.dry_run {
+            return Err(String::from("The json format is only for a --dry-run."));
+        }
+        
+        if parsed.format == Format::Json && parsed.command != Command::Rename {
+            return Err(String::from("The json format is only for renaming."));
+        }
+        
+        Ok(parsed)
+    }
+}
+
+/// The value following an option.
+fn value(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or(format!("Option '{}' needs a value.", option))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    
+    fn parse(args: &str) -> Result<Args, String> {
+        Args::parse(args.split_whitespace().map(String::from))
+    }
+    
+    #[test]
+    fn test_empty() {
+        let args = parse("").unwrap();
+        
+        assert_eq!(Command::Rename, args.command);
+        assert_eq!(None, args.path);
+        assert_eq!(None, args.show_name);
+        assert!(!args.yes);
+        assert_eq!(Mode::Rename, args.mode);
+        assert_eq!(Policy::Abort, args.policy);
+    }
+    
+    #[test]
+    fn test_options() {
+        let args = parse("--show Friends --season 2 -y -r --mode copy --verify downloads").unwrap();
+        
+        assert_eq!(Command::Rename, args.command);
+        assert_eq!(Some(PathBuf::from("downloads")), args.path);
+        assert_eq!(Some(String::from("Friends")), args.show_name);
+        assert_eq!(Some(2), args.season);
+        assert!(args.yes);
+        assert!(args.recursive);
+        assert_eq!(Mode::Copy, args.mode);
+        assert_eq!(Verify::Contents, args.verify);
+    }
+    
+    #[test]
+    fn test_episodes_per_season() {
+        let args = parse("--episodes-per-season 12,13,24").unwrap();
+        assert_eq!(vec![12, 13, 24], args.episodes_per_season);
+    }
+    
+    #[test]
+    fn test_dry_run() {
+        let args = parse("--dry-run --format json").unwrap();
+        
+        assert!(args.dry_run);
+        assert_eq!(Format::Json, args.format);
+    }
+    
+    #[test]
+    fn test_undo() {
+        let args = parse("undo").unwrap();
+        assert_eq!(Command::Undo(None), args.command);
+        
+        let args = parse("undo 1571234567 --yes").unwrap();
+        assert_eq!(Command::Undo(Some(String::from("1571234567"))), args.command);
+        assert!(args.yes);
+    }
+    
+    #[test]
+    fn test_apply() {
+        let args = parse("apply plan.json --on-conflict skip").unwrap();
+        
+        assert_eq!(Command::Apply(PathBuf::from("plan.json")), args.command);
+        assert_eq!(Policy::Skip, args.policy);
+    }
+    
+    #[test]
+    fn test_errors() {
+        let errors = vec![
+            ("--season two", "Season 'two' is not a number."),
+            ("--show", "Option '--show' needs a value."),
+            ("--mode teleport", "Unknown mode 'teleport', try: rename, copy, hardlink, symlink, relative-symlink"),
+            ("--on-conflict panic", "Unknown conflict policy 'panic', try: abort, skip, suffix, larger"),
+            ("--nope", "Unknown option '--nope'."),
+            ("--dry-run --format xml", "Unknown format 'xml', try: text, json"),
+            ("--format json", "The json format is only for a --dry-run."),
+            ("--episodes-per-season 12,,24", "Bad episode count ''."),
+            ("--episodes-per-season 12,0", "Bad episode count '0'."),
+            ("one two", "Unexpected argument 'two'."),
+            ("apply", "Apply needs a plan file."),
+            ("apply plan.json --dry-run --format json", "The json format is only for renaming."),
+        ];
+        
+        for (args, expected) in errors {
+            assert_eq!(Some(String::from(expected)), parse(args).err());
+        }
+    }
+}
@@ -0,0 +1,426 @@
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+
+use crate::conflict::{find_conflicts, same_file};
+use crate::journal::Journal;
+use crate::planner::{plan, temporary_path};
+use crate::transfer::{Mode, Transfer};
+
+/// A file to move, copy or link.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// Whether to replace a file already at the destination.
+    pub replace: bool,
+}
+
+impl Step {
+    pub fn new<F: AsRef<Path>, T: AsRef<Path>>(from: F, to: T) -> Step {
+        Step {
+            from: PathBuf::from(from.as_ref()),
+            to: PathBuf::from(to.as_ref()),
+            replace: false,
+        }
+    }
+}
+
+/// A finished step, with whatever is needed to put it back.
+struct Done<'s> {
+    step: &'s Step,
+    created: Vec<PathBuf>,
+    backup: Option<PathBuf>,
+}
+
+/// Why a batch failed, and whether it was put back.
+#[derive(Debug)]
+pub struct BatchError {
+    pub step: Step,
+    pub error: io::Error,
+    /// Problems putting back the steps already done.
+    pub rollback: Vec<String>,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed on {:?} -> {:?}\n{}",
+            self.step.from,
+            self.step.to,
+            self.error,
+        )?;
+        
+        if self.rollback.is_empty() {
+            write!(f, "\nEverything else was put back.")
+        }
+        else {
+            write!(f, "\nThese couldn't be put back:")?;
+            
+            for err in &self.rollback {
+                write!(f, "\n{}", err)?;
+            }
+            
+            Ok(())
+        }
+    }
+}
+
+/// A batch of file operations.
+/// Either they all happen, or the finished ones are rolled back.
+pub struct Batch<'t> {
+    transfer: &'t Transfer,
+    steps: Vec<Step>,
+}
+
+impl<'t> Batch<'t> {
+    
+    pub fn new(transfer: &'t Transfer) -> Batch<'t> {
+        Batch {
+            transfer,
+            steps: Vec::new(),
+        }
+    }
+    
+    /// Add a file to the batch.
+    pub fn push(&mut self, step: Step) {
+        self.steps.push(step);
+    }
+    
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+    
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+    
+    /// Check every step can happen, before doing any of them.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let mut targets = HashSet::new();
+        
+        for step in &self.steps {
+            if !step.from.is_file() {
+                errors.push(format!("{} is missing.", step.from.display()));
+            }
+            
+            if !targets.insert(&step.to) {
+                errors.push(format!("{} is the target of more than one file.", step.to.display()));
+            }
+            
+            // A file can't become a folder.
+            let blocked = step.to.ancestors()
+                .skip(1)
+                .find(|parent| parent.exists());
+            
+            if let Some(parent) = blocked {
+                if !parent.is_dir() {
+                    errors.push(format!("{} is not a folder.", parent.display()));
+                }
+            }
+        }
+        
+        // Nothing is overwritten unless asked.
+        for i in find_conflicts(&self.steps, self.transfer.mode()) {
+            errors.push(format!("{} already exists.", self.steps[i].to.display()));
+        }
+        
+        if errors.is_empty() {
+            Ok(())
+        }
+        else {
+            Err(errors)
+        }
+    }
+    
+    /// Run every step, recording them in the journal.
+    /// If one fails the rest are put back, newest first.
+    pub fn run(&self, journal: &mut Journal) -> Result<(), BatchError> {
+        // Renames can swap names around, the rest leave the originals.
+        // Copies and links that are already in place are left alone.
+        let steps = match self.transfer.mode() {
+            Mode::Rename => plan(&self.steps),
+            _ => self.steps.iter()
+                .filter(|step| !same_file(&step.from, &step.to))
+                .cloned()
+                .collect(),
+        };
+        
+        let mut done: Vec<Done> = Vec::new();
+        
+        for step in &steps {
+            let result = self.run_step(step)
+                .and_then(|finished| {
+                    done.push(finished);
+                    journal.record(self.transfer.mode(), &step.from, &step.to)
+                });
+            
+            if let Err(err) = result {
+                return Err(BatchError {
+                    step: step.clone(),
+                    error: err,
+                    rollback: self.rollback(&done),
+                });
+            }
+        }
+        
+        // Replaced files are only gone once everything else worked.
+        for finished in &done {
+            if let Some(backup) = &finished.backup {
+                fs::remove_file(backup).unwrap_or(());
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Run one step, cleaning up after itself if it fails.
+    fn run_step<'s>(&self, step: &'s Step) -> io::Result<Done<'s>> {
+        let created = create_folders(&step.to)?;
+        
+        // Park the file being replaced, in case of a rollback.
+        let backup = if step.replace {
+            let backup = temporary_path(&step.to);
+            
+            if let Err(err) = fs::rename(&step.to, &backup) {
+                remove_folders(&created);
+                return Err(err);
+            }
+            
+            Some(backup)
+        }
+        else {
+            None
+        };
+        
+        if let Err(err) = self.transfer.apply(&step.from, &step.to) {
+            if let Some(backup) = &backup {
+                fs::rename(backup, &step.to).unwrap_or(());
+            }
+            
+            remove_folders(&created);
+            return Err(err);
+        }
+        
+        Ok(Done {
+            step,
+            created,
+            backup,
+        })
+    }
+    
+    /// Put back finished steps, returning anything that failed.
+    fn rollback(&self, done: &[Done]) -> Vec<String> {
+        let mut errors = Vec::new();
+        
+        for finished in done.iter().rev() {
+            let step = finished.step;
+            
+            let result = self.transfer.revert(&step.from, &step.to)
+                .and_then(|_| match &finished.backup {
+                    Some(backup) => fs::rename(backup, &step.to),
+                    None => Ok(()),
+                });
+            
+            match result {
+                Ok(()) => remove_folders(&finished.created),
+                Err(err) => errors.push(format!("{:?} -> {:?}: {}", step.to, step.from, err)),
+            }
+        }
+        
+        errors
+    }
+}
+
+/// Create the missing parent folders of a path.
+/// This returns the created folders, deepest first.
+fn create_folders(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let missing: Vec<PathBuf> = path.ancestors()
+        .skip(1)
+        .take_while(|parent| !parent.as_os_str().is_empty() && !parent.exists())
+        .map(PathBuf::from)
+        .collect();
+    
+    for folder in missing.iter().rev() {
+        fs::create_dir(folder)?;
+    }
+    
+    Ok(missing)
+}
+
+/// Remove folders if they're empty, deepest first.
+fn remove_folders(folders: &[PathBuf]) {
+    for folder in folders {
+        if fs::remove_dir(folder).is_err() {
+            break;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use crate::transfer::Verify;
+    
+    fn no_progress(_: u64, _: u64) {}
+    
+    fn create_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("tv-rename-batch-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    #[test]
+    fn test_run() {
+        let dir = create_dir("run");
+        fs::write(dir.join("one.mp4"), "one").unwrap();
+        fs::write(dir.join("two.mp4"), "two").unwrap();
+        
+        let transfer = Transfer::new(Mode::Rename, Verify::Size, no_progress);
+        let mut batch = Batch::new(&transfer);
+        batch.push(Step::new(dir.join("one.mp4"), dir.join("show/S01E01.mp4")));
+        batch.push(Step::new(dir.join("two.mp4"), dir.join("show/S01E02.mp4")));
+        
+        let mut journal = Journal::create(dir.join("journals")).unwrap();
+        
+        batch.validate().unwrap();
+        batch.run(&mut journal).unwrap();
+        
+        assert_eq!("one", fs::read_to_string(dir.join("show/S01E01.mp4")).unwrap());
+        assert_eq!("two", fs::read_to_string(dir.join("show/S01E02.mp4")).unwrap());
+    }
+    
+    #[test]
+    fn test_run_swap() {
+        let dir = create_dir("swap");
+        fs::write(dir.join("S01E02.mp4"), "one").unwrap();
+        fs::write(dir.join("S01E01.mp4"), "two").unwrap();
+        
+        let transfer = Transfer::new(Mode::Rename, Verify::Size, no_progress);
+        let mut batch = Batch::new(&transfer);
+        batch.push(Step::new(dir.join("S01E02.mp4"), dir.join("S01E01.mp4")));
+        batch.push(Step::new(dir.join("S01E01.mp4"), dir.join("S01E02.mp4")));
+        
+        let mut journal = Journal::create(dir.join("journals")).unwrap();
+        
+        batch.validate().unwrap();
+        batch.run(&mut journal).unwrap();
+        
+        assert_eq!("one", fs::read_to_string(dir.join("S01E01.mp4")).unwrap());
+        assert_eq!("two", fs::read_to_string(dir.join("S01E02.mp4")).unwrap());
+        assert_eq!(3, fs::read_dir(&dir).unwrap().count());
+    }
+    
+    #[test]
+    fn test_run_replace() {
+        let dir = create_dir("replace");
+        fs::write(dir.join("one.mp4"), "new").unwrap();
+        fs::write(dir.join("S01E01.mp4"), "old").unwrap();
+        
+        let transfer = Transfer::new(Mode::Rename, Verify::Size, no_progress);
+        let mut batch = Batch::new(&transfer);
+        
+        let mut step = Step::new(dir.join("one.mp4"), dir.join("S01E01.mp4"));
+        step.replace = true;
+        batch.push(step);
+        
+        let mut journal = Journal::create(dir.join("journals")).unwrap();
+        
+        batch.validate().unwrap();
+        batch.run(&mut journal).unwrap();
+        
+        assert_eq!("new", fs::read_to_string(dir.join("S01E01.mp4")).unwrap());
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+    }
+    
+    #[test]
+    fn test_rollback_replace() {
+        let dir = create_dir("rollback-replace");
+        fs::write(dir.join("one.mp4"), "new").unwrap();
+        fs::write(dir.join("S01E01.mp4"), "old").unwrap();
+        
+        let transfer = Transfer::new(Mode::Rename, Verify::Size, no_progress);
+        let mut batch = Batch::new(&transfer);
+        
+        let mut step = Step::new(dir.join("one.mp4"), dir.join("S01E01.mp4"));
+        step.replace = true;
+        batch.push(step);
+        batch.push(Step::new(dir.join("two.mp4"), dir.join("S01E02.mp4")));
+        
+        let mut journal = Journal::create(dir.join("journals")).unwrap();
+        batch.run(&mut journal).unwrap_err();
+        
+        assert_eq!("new", fs::read_to_string(dir.join("one.mp4")).unwrap());
+        assert_eq!("old", fs::read_to_string(dir.join("S01E01.mp4")).unwrap());
+        assert_eq!(3, fs::read_dir(&dir).unwrap().count());
+    }
+    
+    #[test]
+    fn test_rollback() {
+        let dir = create_dir("rollback");
+        fs::write(dir.join("one.mp4"), "one").unwrap();
+        fs::write(dir.join("two.mp4"), "two").unwrap();
+        
+        let transfer = Transfer::new(Mode::Rename, Verify::Size, no_progress);
+        let mut batch = Batch::new(&transfer);
+        batch.push(Step::new(dir.join("one.mp4"), dir.join("show/S01E01.mp4")));
+        // This one has gone missing since.
+        batch.push(Step::new(dir.join("three.mp4"), dir.join("show/S01E03.mp4")));
+        batch.push(Step::new(dir.join("two.mp4"), dir.join("show/S01E02.mp4")));
+        
+        let mut journal = Journal::create(dir.join("journals")).unwrap();
+        let err = batch.run(&mut journal).unwrap_err();
+        
+        assert_eq!(dir.join("three.mp4"), err.step.from);
+        assert_eq!(io::ErrorKind::NotFound, err.error.kind());
+        assert!(err.rollback.is_empty());
+        
+        // Back where they started, without the new folder.
+        assert_eq!("one", fs::read_to_string(dir.join("one.mp4")).unwrap());
+        assert_eq!("two", fs::read_to_string(dir.join("two.mp4")).unwrap());
+        assert!(!dir.join("show").exists());
+    }
+    
+    #[test]
+    fn test_validate() {
+        let dir = create_dir("validate");
+        fs::write(dir.join("one.mp4"), "one").unwrap();
+        fs::write(dir.join("two.mp4"), "two").unwrap();
+        fs::write(dir.join("show"), "not a folder").unwrap();
+        
+        let transfer = Transfer::new(Mode::Rename, Verify::Size, no_progress);
+        let mut batch = Batch::new(&transfer);
+        batch.push(Step::new(dir.join("one.mp4"), dir.join("S01E01.mp4")));
+        batch.push(Step::new(dir.join("two.mp4"), dir.join("S01E01.mp4")));
+        batch.push(Step::new(dir.join("three.mp4"), dir.join("show/S01E03.mp4")));
+        
+        let errors = batch.validate().unwrap_err();
+        
+        assert_eq!(3, errors.len());
+        assert!(errors[0].ends_with("is the target of more than one file."));
+        assert!(errors[1].ends_with("three.mp4 is missing."));
+        assert!(errors[2].ends_with("show is not a folder."));
+    }
+    
+    #[test]
+    fn test_validate_exists() {
+        let dir = create_dir("validate-exists");
+        fs::write(dir.join("one.mp4"), "one").unwrap();
+        fs::write(dir.join("S01E01.mp4"), "old").unwrap();
+        
+        let transfer = Transfer::new(Mode::Rename, Verify::Size, no_progress);
+        let mut batch = Batch::new(&transfer);
+        batch.push(Step::new(dir.join("one.mp4"), dir.join("S01E01.mp4")));
+        
+        let errors = batch.validate().unwrap_err();
+        
+        assert_eq!(1, errors.len());
+        assert!(errors[0].ends_with("S01E01.mp4 already exists."));
+    }
+}
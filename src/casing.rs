@@ -0,0 +1,263 @@
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::config::get_config_path;
+
+/// Default acronyms, kept in capitals.
+pub const ACRONYMS: [&str; 16] = [
+    "BBC",
+    "CIA",
+    "CSI",
+    "DC",
+    "FBI",
+    "JAG",
+    "NASA",
+    "NBA",
+    "NCIS",
+    "NFL",
+    "NYPD",
+    "SWAT",
+    "TV",
+    "UFO",
+    "UK",
+    "USA",
+];
+
+/// Little words, only capitalised at the start of a title.
+const SMALL_WORDS: [&str; 20] = [
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "in",
+    "nor", "of", "on", "or", "per", "the", "to", "via", "vs", "with",
+];
+
+lazy_static! {
+    /// Roman numerals up to 39, like 'Rocky IV' or 'Part XII'.
+    /// Any higher and they're mostly words, like 'mix' or 'liv'.
+    static ref ROMAN: Regex = Regex::new(r"^x{0,3}(ix|iv|v?i{0,3})$").unwrap();
+}
+
+/// Title casing, in English.
+/// - Small words are lowercase, except the first.
+/// - Acronyms and roman numerals are in capitals.
+/// - Words that are already mixed case, like 'McDonald', are kept.
+#[derive(Clone, Debug)]
+pub struct Casing {
+    acronyms: HashSet<String>,
+}
+
+impl Casing {
+    /// For testing.
+    #[allow(dead_code)]
+    pub fn new<S: ToString>(acronyms: Vec<S>) -> Casing {
+        Casing {
+            acronyms: acronyms.iter().map(|word| word.to_string().to_lowercase()).collect(),
+        }
+    }
+    
+    /// Load a list of acronyms, one per line.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Casing, io::Error> {
+        fs::read_to_string(path).map(|contents| {
+            Casing {
+                acronyms: parse_acronyms(contents),
+            }
+        })
+    }
+    
+    pub fn size(&self) -> usize {
+        self.acronyms.len()
+    }
+    
+    /// Case one word, the first word of a title is always capitalised.
+    pub fn word(&self, word: &str, first: bool) -> String {
+        let lower = word.to_lowercase();
+        
+        if is_mixed(word) {
+            String::from(word)
+        }
+        else if self.acronyms.contains(&lower) || ROMAN.is_match(&lower) {
+            word.to_uppercase()
+        }
+        else if !first && SMALL_WORDS.contains(&lower.as_str()) {
+            lower
+        }
+        else {
+            let mut chars = lower.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars).collect(),
+                None => lower,
+            }
+        }
+    }
+    
+    /// Case the words in some text, keeping what's around them, like '(us)'.
+    pub fn text(&self, text: &str, first: bool) -> String {
+        let mut output = String::new();
+        let mut rest = text;
+        
+        for (i, word) in words(text).into_iter().enumerate() {
+            let start = rest.find(word).unwrap_or(0);
+            
+            output.push_str(&rest[..start]);
+            output.push_str(&self.word(word, first && i == 0));
+            rest = &rest[start + word.len()..];
+        }
+        
+        output.push_str(rest);
+        output
+    }
+}
+
+/// Split text into words, keeping apostrophes like in "don't".
+pub fn words(text: &str) -> Vec<&str> {
+    text.split(|c: char| !c.is_alphanumeric() && !is_apostrophe(c))
+        .map(|word| word.trim_matches(is_apostrophe))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn is_apostrophe(c: char) -> bool {
+    c == '\'' || c == '’'
+}
+
+/// A capital after a lowercase letter, like 'iCarly' or 'McDonald'.
+/// Words in all capitals aren't mixed, they're usually shouting.
+fn is_mixed(word: &str) -> bool {
+    let mut lower = false;
+    
+    for c in word.chars() {
+        if c.is_lowercase() {
+            lower = true;
+        }
+        else if lower && c.is_uppercase() {
+            return true;
+        }
+    }
+    
+    false
+}
+
+/// Acronyms are stored lowercase, comments are skipped.
+fn parse_acronyms<S: AsRef<str>>(contents: S) -> HashSet<String> {
+    contents.as_ref().to_lowercase().split("\n")
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty() && !s.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// Write the default acronym list to file.
+pub fn write_acronyms<P: AsRef<Path>>(path: P) -> Result<(), io::Error> {
+    let mut file = File::create(path)?;
+    
+    writeln!(file, "# Acronyms, kept in capitals. One per line.")?;
+    
+    for acronym in &ACRONYMS {
+        writeln!(file, "{}", acronym)?;
+    }
+    
+    Ok(())
+}
+
+/// Get the acronyms path.
+pub fn get_acronyms_path() -> Result<PathBuf, &'static str> {
+    let path = get_config_path("acronyms.txt")?;
+    
+    // Write a fresh file if it doesn't already exist.
+    if !path.exists() && write_acronyms(&path).is_err() {
+        return Err("Failed to write acronyms file.");
+    }
+    
+    Ok(path)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    
+    fn title(casing: &Casing, text: &str) -> String {
+        words(text).iter()
+            .enumerate()
+            .map(|(i, word)| casing.word(word, i == 0))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+    
+    #[test]
+    fn test_small_words() {
+        let casing = Casing::new(ACRONYMS.to_vec());
+        
+        assert_eq!("The One with the Baby Shower", title(&casing, "the.one.with.the.baby.shower"));
+        assert_eq!("A Tale of Two Cities", title(&casing, "A.TALE.OF.TWO.CITIES"));
+        assert_eq!("Georgia on My Mind", title(&casing, "georgia on my mind"));
+        assert_eq!("The One Where Rachel's Sister Can't Go", title(&casing, "the.one.where.rachel's.sister.can't.go"));
+    }
+    
+    #[test]
+    fn test_acronyms() {
+        let casing = Casing::new(ACRONYMS.to_vec());
+        
+        assert_eq!("CSI", title(&casing, "csi"));
+        assert_eq!("NYPD Blue", title(&casing, "Nypd.Blue"));
+        assert_eq!("The UFO Files", title(&casing, "the.ufo.files"));
+        
+        // Only what's listed.
+        let casing = Casing::new(vec!["ncis"]);
+        assert_eq!("NCIS Csi", title(&casing, "ncis.csi"));
+    }
+    
+    #[test]
+    fn test_roman() {
+        let casing = Casing::new(ACRONYMS.to_vec());
+        
+        assert_eq!("Rocky IV", title(&casing, "rocky.iv"));
+        assert_eq!("Part XII", title(&casing, "part.xii"));
+        assert_eq!("I Love Lucy", title(&casing, "i.love.lucy"));
+        assert_eq!("Mix Tape", title(&casing, "mix.tape"));
+        assert_eq!("Vivid Civil", title(&casing, "vivid.civil"));
+    }
+    
+    #[test]
+    fn test_mixed() {
+        let casing = Casing::new(ACRONYMS.to_vec());
+        
+        assert_eq!("iCarly", title(&casing, "iCarly"));
+        assert_eq!("The McDonald Case", title(&casing, "THE.McDonald.CASE"));
+        assert_eq!("MacGyver", title(&casing, "MacGyver"));
+        assert!(is_mixed("eBay"));
+        assert!(!is_mixed("HDTV"));
+        assert!(!is_mixed("Closer"));
+    }
+    
+    #[test]
+    fn test_text() {
+        let casing = Casing::new(ACRONYMS.to_vec());
+        
+        assert_eq!("Don't", casing.text("don't", true));
+        assert_eq!("(Us)", casing.text("(us)", false));
+        assert_eq!("and", casing.text("and", false));
+        assert_eq!("And-the-End", casing.text("and-the-end", true));
+        assert_eq!("...", casing.text("...", true));
+    }
+    
+    #[test]
+    fn test_words() {
+        assert_eq!(vec!["show", "name", "don't"], words("show.name-[don't]"));
+        assert_eq!(vec!["rock", "n", "roll"], words("'rock.'n'.roll'"));
+        assert_eq!(Vec::<&str>::new(), words("..."));
+    }
+    
+    #[test]
+    fn test_load() {
+        let casing = Casing::load(Path::new("test/acronyms.txt")).unwrap();
+        
+        assert_eq!(3, casing.size());
+        assert_eq!("CSI Miami", title(&casing, "csi.miami"));
+        assert_eq!("Ufo", title(&casing, "ufo"));
+    }
+}
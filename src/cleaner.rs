@@ -1,63 +1,350 @@
 
 use std::fs;
-use std::io;
+use std::mem;
 use std::path::Path;
-use inflector::Inflector;
+use regex::{self, Regex};
 
-// @todo Could these rules be regex?
-fn parse_rules<S: AsRef<str>>(contents: S) -> Vec<String> {
-    contents.as_ref().to_lowercase().split("\n")
-        .map(|s| String::from(s.trim()))
+use crate::casing::{Casing, ACRONYMS, words};
+
+/// Parse rules, one per line.
+/// Blank lines and '#' comments are skipped. A section like '[show-name]'
+/// scopes the rules after it, rules before any section apply to all.
+/// Every bad pattern is reported, by line number.
+fn parse_rules<S: AsRef<str>>(contents: S) -> Result<Vec<(Scope, Rule, String)>, String> {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+    let mut scope = Scope::All;
+    
+    for (i, line) in contents.as_ref().split("\n").enumerate() {
+        let line = line.trim();
+        
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        
+        if let Some(section) = Scope::parse(line) {
+            scope = section;
+            continue;
+        }
+        
+        match parse_line(line) {
+            Ok((rule, replace)) => rules.push((scope, rule, replace)),
+            Err(err) => errors.push(format!("line {}: {}", i + 1, err)),
+        }
+    }
+    
+    if errors.is_empty() {
+        Ok(rules)
+    }
+    else {
+        Err(errors.join("\n"))
+    }
+}
+
+/// A rule and what it's replaced with, like 'dont => don't'.
+/// Plain rules are replaced with nothing.
+fn parse_line(line: &str) -> Result<(Rule, String), String> {
+    let mut split = line.splitn(2, "=>");
+    let rule = split.next().unwrap_or("").trim();
+    let replace = split.next().unwrap_or("").trim();
+    
+    if rule.is_empty() {
+        return Err(String::from("Nothing to replace."));
+    }
+    
+    Ok((Rule::parse(rule)?, String::from(replace)))
+}
+
+/// Where rules are used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scope {
+    All,
+    ShowName,
+    EpisodeName,
+}
+
+impl Scope {
+    /// A section header, like '[show-name]'.
+    /// Other lines in brackets are just rules, like '[ettv]'.
+    fn parse(line: &str) -> Option<Scope> {
+        match line.to_lowercase().as_ref() {
+            "[all]" => Some(Scope::All),
+            "[show-name]" => Some(Scope::ShowName),
+            "[episode-name]" => Some(Scope::EpisodeName),
+            _ => None,
+        }
+    }
+}
+
+/// Something to remove or replace.
+#[derive(Clone, Debug)]
+pub enum Rule {
+    /// Whole words only, between separators.
+    Word(String),
+    /// Anywhere, even inside a word. These are written like 'sub:rip'.
+    Substring(String),
+    /// Any matches of a regex, like 're:x26[45]'.
+    Regex(Regex),
+    /// Whole words that match a glob, like 'glob:*rip'.
+    Glob(Regex),
+}
+
+impl Rule {
+    /// Regexes and globs are compiled here, so errors are found early.
+    pub fn parse<S: AsRef<str>>(line: S) -> Result<Rule, String> {
+        let line = line.as_ref().trim();
+        
+        if let Some(pattern) = line.strip_prefix("re:") {
+            Regex::new(&format!("(?i){}", pattern))
+                .map(Rule::Regex)
+                .map_err(|err| format!("Bad regex '{}', {}.", pattern, describe(err)))
+        }
+        else if let Some(pattern) = line.strip_prefix("glob:") {
+            Regex::new(&glob_to_regex(pattern))
+                .map(Rule::Glob)
+                .map_err(|err| format!("Bad glob '{}', {}.", pattern, describe(err)))
+        }
+        else if let Some(word) = line.strip_prefix("sub:") {
+            Ok(Rule::Substring(word.to_lowercase()))
+        }
+        else {
+            Ok(Rule::Word(line.to_lowercase()))
+        }
+    }
+    
+    /// Find this in some lowercase text, as (start, end) ranges.
+    fn find(&self, text: &str) -> Vec<(usize, usize)> {
+        let found: Vec<(usize, usize)> = match self {
+            Rule::Word(word) | Rule::Substring(word) if word.is_empty() => Vec::new(),
+            Rule::Word(word) => {
+                whole_words(text, text.match_indices(word.as_str()).map(|(i, m)| (i, i + m.len())))
+            },
+            Rule::Substring(part) => {
+                text.match_indices(part.as_str()).map(|(i, m)| (i, i + m.len())).collect()
+            },
+            Rule::Regex(regex) => {
+                regex.find_iter(text).map(|m| (m.start(), m.end())).collect()
+            },
+            Rule::Glob(regex) => {
+                whole_words(text, regex.find_iter(text).map(|m| (m.start(), m.end())))
+            },
+        };
+        
+        found.into_iter()
+            .filter(|(start, end)| end > start)
+            .collect()
+    }
+}
+
+// Regexes can't be compared, but their patterns can.
+impl PartialEq for Rule {
+    fn eq(&self, other: &Rule) -> bool {
+        match (self, other) {
+            (Rule::Word(a), Rule::Word(b)) => a == b,
+            (Rule::Substring(a), Rule::Substring(b)) => a == b,
+            (Rule::Regex(a), Rule::Regex(b)) => a.as_str() == b.as_str(),
+            (Rule::Glob(a), Rule::Glob(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+/// Keep the (start, end) matches that are whole words.
+/// Like 'hd' in 'show.hd.720p' but not 'shadow'. Matches that start or end
+/// with a symbol, like '[ettv]' or '&', don't need a separator there.
+fn whole_words<I: Iterator<Item = (usize, usize)>>(text: &str, matches: I) -> Vec<(usize, usize)> {
+    matches
+        .filter(|&(start, end)| {
+            let first = text[start..end].chars().next();
+            let last = text[start..end].chars().next_back();
+            let before = text[..start].chars().next_back();
+            let after = text[end..].chars().next();
+            
+            !(is_word(first) && is_word(before) || is_word(last) && is_word(after))
+        })
+        .collect()
+}
+
+/// Letters and numbers, the ends of the text aren't.
+fn is_word(c: Option<char>) -> bool {
+    c.is_some_and(char::is_alphanumeric)
+}
+
+/// Part of a name while it's being cleaned.
+#[derive(Clone, Debug, PartialEq)]
+enum Piece {
+    /// Still to be cleaned and title cased.
+    Raw(String),
+    /// Replaced text, left alone by later rules.
+    Fixed(String),
+}
+
+/// Remove or replace everything a rule finds in the raw pieces.
+fn apply(pieces: Vec<Piece>, rule: &Rule, replace: &str) -> Vec<Piece> {
+    let mut output = Vec::new();
+    
+    for piece in pieces {
+        let text = match piece {
+            Piece::Raw(text) => text,
+            fixed => {
+                output.push(fixed);
+                continue;
+            },
+        };
+        
+        let mut raw = String::new();
+        let mut last = 0;
+        
+        for (start, end) in rule.find(&lowercase(&text)) {
+            raw.push_str(&text[last..start]);
+            last = end;
+            
+            // Removing something doesn't split the piece.
+            if !replace.is_empty() {
+                output.push(Piece::Raw(mem::take(&mut raw)));
+                output.push(Piece::Fixed(String::from(replace)));
+            }
+        }
+        
+        raw.push_str(&text[last..]);
+        output.push(Piece::Raw(raw));
+    }
+    
+    output
+}
+
+/// In a glob, '*' is any letters or numbers and '?' is one of them.
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("(?i)");
+    
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(r"[\p{L}\p{N}]*"),
+            '?' => pattern.push_str(r"[\p{L}\p{N}]"),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    
+    pattern
+}
+
+/// Just the last line of a regex error, without the pointer.
+fn describe(err: regex::Error) -> String {
+    let err = err.to_string();
+    let line = err.lines().last().unwrap_or("");
+    
+    String::from(line.trim_start_matches("error: "))
+}
+
+/// Lowercase, for finding rules in text that keeps its casing.
+/// Letters that would change length are left alone, so the matches line up.
+fn lowercase(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            let mut lower = c.to_lowercase();
+            match (lower.next(), lower.next()) {
+                (Some(l), None) if l.len_utf8() == c.len_utf8() => l,
+                _ => c,
+            }
+        })
         .collect()
 }
 
 /// Clean strings.
-/// - Removes strings provided by an 'exclude' file.
+/// - Removes or replaces strings provided by an 'exclude' file, in order.
 /// - Replaces separators with white space.
-/// - Formats in 'Title Case'.
+/// - Formats in 'Title Case', except for replacements.
 pub struct Cleaner {
-    pub(in crate) rules: Vec<String>,
+    pub(in crate) rules: Vec<(Scope, Rule, String)>,
+    casing: Casing,
 }
 
 impl Cleaner {
-    /// For testing.
+    /// For testing, these rules apply to all.
     #[allow(dead_code)]
     pub fn new<S: ToString>(rules: Vec<S>) -> Cleaner {
         Cleaner {
-            rules: rules.iter().map(|rule| rule.to_string()).collect()
+            rules: rules.iter()
+                .map(|line| parse_line(&line.to_string()).unwrap())
+                .map(|(rule, replace)| (Scope::All, rule, replace))
+                .collect(),
+            casing: Casing::new(ACRONYMS.to_vec()),
         }
     }
     
-    /// Load a set of rules.
-    pub fn load<P: AsRef<Path>>(path: P) -> Result<Cleaner, io::Error> {
-        fs::read_to_string(path).map(|contents| {
-            Cleaner {
-                rules: parse_rules(contents)
-            }
+    /// Load a set of rules, and the acronyms for title casing.
+    pub fn load<P: AsRef<Path>, Q: AsRef<Path>>(path: P, acronyms_path: Q) -> Result<Cleaner, String> {
+        let contents = fs::read_to_string(path.as_ref())
+            .map_err(|err| format!("Failed to read {}: {}", path.as_ref().display(), err))?;
+        
+        let rules = parse_rules(contents)
+            .map_err(|err| format!("Bad rules in {}\n{}", path.as_ref().display(), err))?;
+        
+        let casing = Casing::load(acronyms_path.as_ref())
+            .map_err(|err| format!("Failed to read {}: {}", acronyms_path.as_ref().display(), err))?;
+        
+        Ok(Cleaner {
+            rules,
+            casing,
         })
     }
     
-    /// Clean this text.
-    pub fn clean<T: AsRef<str>>(&self, text: T) -> String {
-        let mut working = text.as_ref().to_lowercase();
+    /// Clean this text, with the rules for all and for this scope.
+    pub fn clean<T: AsRef<str>>(&self, text: T, scope: Scope) -> String {
+        let mut pieces = vec![Piece::Raw(String::from(text.as_ref()))];
+        
+        let rules = self.rules.iter()
+            .filter(|(only, _, _)| *only == Scope::All || *only == scope);
         
-        for rule in &self.rules {
-            working = working.replace(rule.as_str(), "");
+        for (_, rule, replace) in rules {
+            pieces = apply(pieces, rule, replace);
+        }
+        
+        // Only the first word of the whole name counts as the start.
+        let mut output: Vec<String> = Vec::new();
+        
+        for piece in &pieces {
+            match piece {
+                Piece::Raw(text) => {
+                    for word in words(text) {
+                        let cased = self.casing.word(word, output.is_empty());
+                        output.push(cased);
+                    }
+                },
+                Piece::Fixed(text) => {
+                    // Words written with capitals, like 'Mr.' or '(US)', are kept.
+                    for word in text.split_whitespace() {
+                        let cased = if word.chars().any(char::is_uppercase) {
+                            String::from(word)
+                        }
+                        else {
+                            self.casing.text(word, output.is_empty())
+                        };
+                        
+                        output.push(cased);
+                    }
+                },
+            }
         }
         
-        // @todo How could we do non-caps for 'with' 'to' 'the'?
-        return working.to_title_case();
+        output.join(" ")
     }
     
     pub fn size(&self) -> usize {
         self.rules.len()
     }
+    
+    /// How many acronyms are kept in capitals.
+    pub fn acronyms(&self) -> usize {
+        self.casing.size()
+    }
 }
 
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::exclude_rules::EXCLUDE_RULES;
     
     #[test]
     fn test_clean() {
@@ -65,16 +352,171 @@ mod test {
         
         assert_eq!(4, cleaner.size());
         
-        let actual = cleaner.clean("yep.okay.SURe[H264][AC3]-LOL");
+        let actual = cleaner.clean("yep.okay.SURe[H264][AC3]-LOL", Scope::EpisodeName);
         let expected = "Yep Okay Sure";
         
         assert_eq!(expected, actual);
     }
     
+    #[test]
+    fn test_clean_words() {
+        let cleaner = Cleaner::new(EXCLUDE_RULES.to_vec());
+        
+        let titles = vec![
+            (".the.shadow.line.720p.hdtv.x264-yify", "The Shadow Line"),
+            (".georgia.on.my.mind.hd", "Georgia on My Mind"),
+            (".the.comedy.of.errors.webrip", "The Comedy of Errors"),
+            (".one.planet.hdtv.aac", "One Planet"),
+            (".the.internet.com.mp4", "The Internet"),
+            (".the.shadowhd.org", "The Shadowhd"),
+        ];
+        
+        for (title, expected) in titles {
+            assert_eq!(expected, cleaner.clean(title, Scope::EpisodeName));
+        }
+    }
+    
+    #[test]
+    fn test_clean_substring() {
+        let cleaner = Cleaner::new(vec!["sub:hdtv", "720p"]);
+        
+        assert_eq!(Rule::Substring(String::from("hdtv")), cleaner.rules[0].1);
+        assert_eq!(Rule::Word(String::from("720p")), cleaner.rules[1].1);
+        
+        // Glued on tags.
+        assert_eq!("The One", cleaner.clean("the.one.720pHDTV", Scope::EpisodeName));
+        
+        // But words are only whole.
+        assert!(Rule::parse("720p").unwrap().find("the.one.720pweb").is_empty());
+    }
+    
+    #[test]
+    fn test_clean_regex() {
+        let cleaner = Cleaner::new(vec![r"re:x26[45]", r"re:\d{3,4}p", r"re:\[[A-F0-9]{8}\]", r"re:-[A-Za-z]+$"]);
+        
+        assert_eq!("The One", cleaner.clean(".the.one.1080p.x265[ABCD1234]-LOL", Scope::EpisodeName));
+        assert_eq!("The One", cleaner.clean(".the.one.720p.x264-group", Scope::EpisodeName));
+    }
+    
+    #[test]
+    fn test_clean_glob() {
+        let cleaner = Cleaner::new(vec!["glob:*rip", "glob:h?tv"]);
+        
+        assert_eq!("The Tripwire", cleaner.clean(".the.tripwire.webrip.hdtv", Scope::EpisodeName));
+        assert_eq!("The One", cleaner.clean(".the.one.DVDRip.HDTV", Scope::EpisodeName));
+    }
+    
+    #[test]
+    fn test_parse_rules() {
+        let rules = parse_rules("hd\nsub:rip\nre:x26[45]\nglob:*rip").unwrap();
+        
+        assert_eq!((Scope::All, Rule::Word(String::from("hd")), String::new()), rules[0]);
+        assert_eq!((Scope::All, Rule::Substring(String::from("rip")), String::new()), rules[1]);
+        assert_eq!((Scope::All, Rule::parse("re:x26[45]").unwrap(), String::new()), rules[2]);
+        assert_eq!((Scope::All, Rule::parse("glob:*rip").unwrap(), String::new()), rules[3]);
+    }
+    
+    #[test]
+    fn test_parse_rules_errors() {
+        let actual = parse_rules("hd\nre:x26[45\n# re:(\nweb\nre:(unclosed");
+        let expected = "line 2: Bad regex 'x26[45', unclosed character class.\nline 5: Bad regex '(unclosed', unclosed group.";
+        
+        assert_eq!(Err(String::from(expected)), actual);
+    }
+    
+    #[test]
+    fn test_sections() {
+        let rules = parse_rules("# Comment\n\nhdtv\n[show-name]\nus\n[ettv]\n\n[episode-name]\nextended\n[all]\nx264\n").unwrap();
+        
+        assert_eq!(vec![
+            (Scope::All, Rule::Word(String::from("hdtv")), String::new()),
+            (Scope::ShowName, Rule::Word(String::from("us")), String::new()),
+            (Scope::ShowName, Rule::Word(String::from("[ettv]")), String::new()),
+            (Scope::EpisodeName, Rule::Word(String::from("extended")), String::new()),
+            (Scope::All, Rule::Word(String::from("x264")), String::new()),
+        ], rules);
+        
+        let cleaner = Cleaner {
+            rules,
+            casing: Casing::new(ACRONYMS.to_vec()),
+        };
+        
+        assert_eq!("The Office", cleaner.clean("the.office.us.hdtv", Scope::ShowName));
+        assert_eq!("The Extended Cut", cleaner.clean("the.us.extended.cut.hdtv", Scope::ShowName));
+        assert_eq!("The Us Cut", cleaner.clean("the.us.extended.cut.x264", Scope::EpisodeName));
+    }
+    
+    #[test]
+    fn test_clean_symbols() {
+        let cleaner = Cleaner::new(vec!["[ettv]", "-lol"]);
+        
+        // Symbols at the ends don't need separators.
+        assert_eq!("Show", cleaner.clean("show[ettv]", Scope::ShowName));
+        assert_eq!("The One", cleaner.clean("the.one-lol", Scope::EpisodeName));
+        assert_eq!("The One Lolly", cleaner.clean("the.one-lolly", Scope::EpisodeName));
+    }
+    
+    #[test]
+    fn test_replace() {
+        let cleaner = Cleaner::new(vec!["& => and", "dont => don't", "mr => Mr.", "us => (US)", "hdtv"]);
+        
+        assert_eq!("Tom and Jerry", cleaner.clean("tom.&.jerry.hdtv", Scope::ShowName));
+        assert_eq!("Tom and Jerry", cleaner.clean("tom&jerry", Scope::ShowName));
+        assert_eq!("The Office (US)", cleaner.clean("The.Office.US.HDTV", Scope::ShowName));
+        assert_eq!("Mr. Robot", cleaner.clean("mr.robot", Scope::ShowName));
+        assert_eq!("I Don't Know", cleaner.clean(".i.dont.know", Scope::EpisodeName));
+        
+        // Whole words only.
+        assert_eq!("Mrs Brown", cleaner.clean("mrs.brown", Scope::ShowName));
+    }
+    
+    #[test]
+    fn test_replace_order() {
+        // The tag is gone before it can be replaced.
+        let cleaner = Cleaner::new(vec!["hdtv", "hdtv => HD"]);
+        assert_eq!("The One", cleaner.clean("the.one.hdtv", Scope::EpisodeName));
+        
+        let cleaner = Cleaner::new(vec!["hdtv => HD", "hdtv"]);
+        assert_eq!("The One HD", cleaner.clean("the.one.hdtv", Scope::EpisodeName));
+        
+        // Replacements aren't touched by later rules.
+        let cleaner = Cleaner::new(vec!["uk => (UK)", "uk"]);
+        assert_eq!("Top Gear (UK)", cleaner.clean("top.gear.uk", Scope::ShowName));
+    }
+    
+    #[test]
+    fn test_replace_errors() {
+        let actual = parse_rules("hd\n=> nothing\nre:x[ => y");
+        let expected = "line 2: Nothing to replace.\nline 3: Bad regex 'x[', unclosed character class.";
+        
+        assert_eq!(Err(String::from(expected)), actual);
+    }
+    
+    #[test]
+    fn test_title_case() {
+        let cleaner = Cleaner::new(vec!["hdtv"]);
+        
+        assert_eq!("The One with the Baby Shower", cleaner.clean("the.one.with.the.baby.shower.hdtv", Scope::EpisodeName));
+        assert_eq!("CSI Miami", cleaner.clean("CSI.Miami.HDTV", Scope::ShowName));
+        assert_eq!("NYPD Blue", cleaner.clean("nypd.blue", Scope::ShowName));
+        assert_eq!("Star Trek II", cleaner.clean("star.trek.ii.hdtv", Scope::ShowName));
+        
+        // Mixed casing is kept, even around a rule.
+        assert_eq!("iCarly", cleaner.clean("iCarly.HDTV", Scope::ShowName));
+        assert_eq!("The McDonald Case", cleaner.clean("THE.McDonald.CASE.hdtv", Scope::EpisodeName));
+        
+        // Replacements are cased too, unless they have capitals, and can be the start.
+        let cleaner = Cleaner::new(vec!["dr => Dr.", "& => and", "pt => part"]);
+        
+        assert_eq!("Dr. Who and the Daleks", cleaner.clean("dr.who.&.the.daleks", Scope::ShowName));
+        assert_eq!("Tom and the Cat", cleaner.clean("tom&the.cat", Scope::ShowName));
+        assert_eq!("Part II", cleaner.clean("pt.ii", Scope::EpisodeName));
+    }
+    
     #[test]
     fn test_load() {
-        let cleaner = Cleaner::load(Path::new("test/exclude.txt")).unwrap();
+        let cleaner = Cleaner::load(Path::new("test/exclude.txt"), Path::new("test/acronyms.txt")).unwrap();
         
-        assert_eq!(27, cleaner.size());
+        assert_eq!(26, cleaner.size());
     }
 }
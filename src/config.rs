@@ -0,0 +1,24 @@
+
+use std::path::PathBuf;
+
+use directories::ProjectDirs;
+
+/// Get the project directories.
+fn get_dirs() -> Result<ProjectDirs, &'static str> {
+    // @todo Should these be const somewhere?
+    match ProjectDirs::from("com", "gwillz", "tv-rename") {
+        Some(dirs) => Ok(dirs),
+        // Rare? I assume?
+        None => Err("Failed to find config.")
+    }
+}
+
+/// Get the path of a file in the config directory.
+pub fn get_config_path(file_name: &str) -> Result<PathBuf, &'static str> {
+    get_dirs().map(|dirs| dirs.config_dir().with_file_name(file_name))
+}
+
+/// Get the path of a file or folder in the data directory.
+pub fn get_data_path(name: &str) -> Result<PathBuf, &'static str> {
+    get_dirs().map(|dirs| dirs.data_dir().join(name))
+}
@@ -0,0 +1,354 @@
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+
+use crate::batch::Step;
+use crate::sidecar::Sidecar;
+use crate::transfer::Mode;
+
+/// What to do when a destination already exists.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Policy {
+    /// Stop before anything happens.
+    Abort,
+    /// Leave the file where it is.
+    Skip,
+    /// Add a number to the new name, like 'Show S01E01 (2).mkv'.
+    Suffix,
+    /// Keep whichever file is larger.
+    Larger,
+}
+
+impl Policy {
+    /// Get a policy by name, as given on the command line.
+    pub fn parse(name: &str) -> Option<Policy> {
+        match name {
+            "abort" => Some(Policy::Abort),
+            "skip" => Some(Policy::Skip),
+            "suffix" => Some(Policy::Suffix),
+            "larger" => Some(Policy::Larger),
+            _ => None,
+        }
+    }
+}
+
+/// Find the steps that would overwrite an existing file.
+/// A file isn't in its own way, and when renaming, neither is a file that's
+/// about to be moved.
+pub fn find_conflicts(steps: &[Step], mode: Mode) -> Vec<usize> {
+    let moving: HashSet<&PathBuf> = match mode {
+        Mode::Rename => steps.iter().map(|step| &step.from).collect(),
+        _ => HashSet::new(),
+    };
+    
+    steps.iter()
+        .enumerate()
+        .filter(|(_, step)| {
+            !step.replace &&
+            fs::symlink_metadata(&step.to).is_ok() &&
+            !moving.contains(&step.to) &&
+            !same_file(&step.from, &step.to)
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Apply a policy to the steps that would overwrite a file.
+/// This gives the new steps and a note about each conflict, or all the
+/// conflicts if aborting.
+pub fn resolve(steps: Vec<Step>, mode: Mode, policy: Policy) -> Result<(Vec<Step>, Vec<String>), Vec<String>> {
+    let mut steps = steps;
+    let mut notes = Vec::new();
+    
+    // Skipping a rename can leave another file in the way, so go again.
+    loop {
+        let conflicts = find_conflicts(&steps, mode);
+        
+        if conflicts.is_empty() {
+            return Ok((steps, notes));
+        }
+        
+        if policy == Policy::Abort {
+            return Err(conflicts.iter()
+                .map(|i| format!("{} already exists.", steps[*i].to.display()))
+                .collect());
+        }
+        
+        let taken: HashSet<PathBuf> = steps.iter()
+            .map(|step| step.to.clone())
+            .collect();
+        
+        let mut skipped = HashSet::new();
+        
+        for i in conflicts {
+            let step = &mut steps[i];
+            
+            match policy {
+                Policy::Suffix => {
+                    step.to = free_path(&step.to, &taken);
+                    notes.push(format!("Already exists, using {}", step.to.display()));
+                },
+                Policy::Larger if file_size(&step.from) > file_size(&step.to) => {
+                    step.replace = true;
+                    notes.push(format!("Replacing smaller {}", step.to.display()));
+                },
+                Policy::Larger => {
+                    skipped.insert(i);
+                    notes.push(format!("Skipped, larger {} already exists.", step.to.display()));
+                },
+                _ => {
+                    skipped.insert(i);
+                    notes.push(format!("Skipped, {} already exists.", step.to.display()));
+                },
+            }
+        }
+        
+        steps = steps.into_iter()
+            .enumerate()
+            .filter(|(i, _)| !skipped.contains(i))
+            .map(|(_, step)| step)
+            .collect();
+    }
+}
+
+/// Resolve the videos first, so their sidecars can follow them.
+/// A sidecar is named after where its video really goes (like a suffixed
+/// name), and stays where it is if its video was skipped.
+pub fn resolve_with_sidecars(videos: Vec<Step>, sidecars: &[Sidecar], mode: Mode, policy: Policy) -> Result<(Vec<Step>, Vec<String>), Vec<String>> {
+    let (videos, mut notes) = resolve(videos, mode, policy)?;
+    
+    let following: Vec<Step> = sidecars.iter()
+        .filter_map(|sidecar| {
+            videos.iter()
+                .find(|video| video.from == sidecar.episode)
+                .map(|video| Step::new(&sidecar.path, sidecar.follow(&video.to)))
+        })
+        .collect();
+    
+    // The videos are settled, this only touches the sidecars.
+    let (steps, more) = resolve(videos.into_iter().chain(following).collect(), mode, policy)?;
+    notes.extend(more);
+    
+    Ok((steps, notes))
+}
+
+/// Whether two paths are the same file, like renaming 'a.mkv' to 'A.mkv'
+/// on a case insensitive file system.
+#[cfg(unix)]
+pub fn same_file(one: &Path, two: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    
+    match (fs::metadata(one), fs::metadata(two)) {
+        (Ok(one), Ok(two)) => one.dev() == two.dev() && one.ino() == two.ino(),
+        _ => false,
+    }
+}
+
+/// Whether two paths are the same file, like renaming 'a.mkv' to 'A.mkv'
+/// on a case insensitive file system.
+#[cfg(not(unix))]
+pub fn same_file(one: &Path, two: &Path) -> bool {
+    match (one.canonicalize(), two.canonicalize()) {
+        (Ok(one), Ok(two)) => one == two,
+        _ => false,
+    }
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0)
+}
+
+/// Number a path until it's free, like 'name (2).ext'.
+fn free_path(path: &Path, taken: &HashSet<PathBuf>) -> PathBuf {
+    let stem = path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    
+    let extension = path.extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    
+    let mut count = 2;
+    
+    loop {
+        let free = path.with_file_name(format!("{} ({}){}", stem, count, extension));
+        
+        if !free.exists() && !taken.contains(&free) {
+            return free;
+        }
+        
+        count += 1;
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    
+    fn create_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("tv-rename-conflict-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    fn create_steps(dir: &Path) -> Vec<Step> {
+        fs::write(dir.join("one.mp4"), "a bigger episode").unwrap();
+        fs::write(dir.join("two.mp4"), "episode").unwrap();
+        fs::write(dir.join("three.mp4"), "episode").unwrap();
+        fs::write(dir.join("S01E01.mp4"), "old episode").unwrap();
+        fs::write(dir.join("S01E02.mp4"), "old episode").unwrap();
+        
+        vec![
+            Step::new(dir.join("one.mp4"), dir.join("S01E01.mp4")),
+            Step::new(dir.join("two.mp4"), dir.join("S01E02.mp4")),
+            Step::new(dir.join("three.mp4"), dir.join("S01E03.mp4")),
+        ]
+    }
+    
+    #[test]
+    fn test_find() {
+        let dir = create_dir("find");
+        let mut steps = create_steps(&dir);
+        
+        assert_eq!(vec![0, 1], find_conflicts(&steps, Mode::Rename));
+        
+        // Unless the file in the way is moving too.
+        steps.push(Step::new(dir.join("S01E02.mp4"), dir.join("S01E04.mp4")));
+        
+        assert_eq!(vec![0], find_conflicts(&steps, Mode::Rename));
+        assert_eq!(vec![0, 1], find_conflicts(&steps, Mode::Copy));
+    }
+    
+    #[test]
+    fn test_find_self() {
+        let dir = create_dir("self");
+        fs::write(dir.join("S01E01.mp4"), "episode").unwrap();
+        
+        let steps = vec![Step::new(dir.join("S01E01.mp4"), dir.join("S01E01.mp4"))];
+        
+        assert!(find_conflicts(&steps, Mode::Rename).is_empty());
+    }
+    
+    #[test]
+    fn test_abort() {
+        let dir = create_dir("abort");
+        let steps = create_steps(&dir);
+        
+        let errors = resolve(steps, Mode::Rename, Policy::Abort).unwrap_err();
+        
+        assert_eq!(2, errors.len());
+        assert!(errors[0].ends_with("S01E01.mp4 already exists."));
+    }
+    
+    #[test]
+    fn test_skip() {
+        let dir = create_dir("skip");
+        let steps = create_steps(&dir);
+        
+        let (steps, notes) = resolve(steps, Mode::Rename, Policy::Skip).unwrap();
+        
+        assert_eq!(vec![Step::new(dir.join("three.mp4"), dir.join("S01E03.mp4"))], steps);
+        assert_eq!(2, notes.len());
+    }
+    
+    #[test]
+    fn test_skip_chain() {
+        let dir = create_dir("skip-chain");
+        let mut steps = create_steps(&dir);
+        
+        // S01E02 can't move, so two.mp4 is stuck too.
+        fs::write(dir.join("S01E04.mp4"), "old episode").unwrap();
+        steps.push(Step::new(dir.join("S01E02.mp4"), dir.join("S01E04.mp4")));
+        
+        let (steps, _) = resolve(steps, Mode::Rename, Policy::Skip).unwrap();
+        
+        assert_eq!(vec![Step::new(dir.join("three.mp4"), dir.join("S01E03.mp4"))], steps);
+    }
+    
+    #[test]
+    fn test_suffix() {
+        let dir = create_dir("suffix");
+        let mut steps = create_steps(&dir);
+        steps.push(Step::new(dir.join("other.mp4"), dir.join("S01E01 (2).mp4")));
+        
+        let (steps, _) = resolve(steps, Mode::Rename, Policy::Suffix).unwrap();
+        
+        assert_eq!(dir.join("S01E01 (3).mp4"), steps[0].to);
+        assert_eq!(dir.join("S01E02 (2).mp4"), steps[1].to);
+        assert_eq!(dir.join("S01E03.mp4"), steps[2].to);
+    }
+    
+    fn create_sidecars(dir: &Path) -> Vec<Sidecar> {
+        ["one", "three"].iter()
+            .map(|name| {
+                fs::write(dir.join(format!("{}.en.srt", name)), "subtitles").unwrap();
+                
+                Sidecar {
+                    path: dir.join(format!("{}.en.srt", name)),
+                    episode: dir.join(format!("{}.mp4", name)),
+                    suffix: String::from(".en"),
+                    extension: String::from("srt"),
+                }
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn test_sidecars_suffix() {
+        let dir = create_dir("sidecars-suffix");
+        let steps = create_steps(&dir);
+        let sidecars = create_sidecars(&dir);
+        
+        let (steps, _) = resolve_with_sidecars(steps, &sidecars, Mode::Rename, Policy::Suffix).unwrap();
+        
+        assert_eq!(5, steps.len());
+        assert_eq!(Step::new(dir.join("one.en.srt"), dir.join("S01E01 (2).en.srt")), steps[3]);
+        assert_eq!(Step::new(dir.join("three.en.srt"), dir.join("S01E03.en.srt")), steps[4]);
+    }
+    
+    #[test]
+    fn test_sidecars_skip() {
+        let dir = create_dir("sidecars-skip");
+        let steps = create_steps(&dir);
+        let sidecars = create_sidecars(&dir);
+        
+        let (steps, _) = resolve_with_sidecars(steps, &sidecars, Mode::Rename, Policy::Skip).unwrap();
+        
+        // The subtitles for one.mp4 stay with it.
+        assert_eq!(vec![
+            Step::new(dir.join("three.mp4"), dir.join("S01E03.mp4")),
+            Step::new(dir.join("three.en.srt"), dir.join("S01E03.en.srt")),
+        ], steps);
+    }
+    
+    #[test]
+    fn test_sidecars_in_the_way() {
+        let dir = create_dir("sidecars-in-the-way");
+        let steps = create_steps(&dir);
+        let sidecars = create_sidecars(&dir);
+        fs::write(dir.join("S01E03.en.srt"), "old subtitles").unwrap();
+        
+        let (steps, notes) = resolve_with_sidecars(steps, &sidecars, Mode::Rename, Policy::Skip).unwrap();
+        
+        assert_eq!(vec![Step::new(dir.join("three.mp4"), dir.join("S01E03.mp4"))], steps);
+        assert_eq!(3, notes.len());
+    }
+    
+    #[test]
+    fn test_larger() {
+        let dir = create_dir("larger");
+        let steps = create_steps(&dir);
+        
+        let (steps, _) = resolve(steps, Mode::Rename, Policy::Larger).unwrap();
+        
+        assert_eq!(2, steps.len());
+        assert_eq!(dir.join("S01E01.mp4"), steps[0].to);
+        assert!(steps[0].replace);
+        assert_eq!(dir.join("S01E03.mp4"), steps[1].to);
+        assert!(!steps[1].replace);
+    }
+}
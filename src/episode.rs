@@ -1,59 +1,131 @@
 
-use std::fs;
-use std::io;
 use std::fmt;
 use std::hash;
-use std::path::PathBuf;
+use std::rc::Rc;
+use std::path::{Path, PathBuf};
 use std::cmp::{self, Ordering};
 
+use crate::template::Template;
+
+
+/// When a daily show aired.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AirDate {
+    pub year: u32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl fmt::Display for AirDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
 
 /// This represents an old and new paths of an episode.
 #[derive(Clone)]
 pub struct Episode {
     pub path: PathBuf,
     pub episode: u32,
+    pub last_episode: u32,
     pub season: u32,
     pub name: String,
     pub show_name: String,
+    pub year: Option<u32>,
     pub extension: String,
+    pub template: Rc<Template>,
+    pub library: Option<PathBuf>,
+    /// Which parser rule found the episode number.
+    pub rule: &'static str,
+    /// Daily shows are known by date instead. These are numbered by
+    /// the year as season, and the month and day as episode.
+    pub date: Option<AirDate>,
+    /// Anime counts episodes from the start of the show, ignoring seasons.
+    pub absolute: Option<u32>,
 }
 
 impl Episode {
     /// The unique identifier for an episode.
+    /// Multi-episode files are written as a range, like S01E01-E02.
+    /// Daily shows are the date, like 2019-03-14.
     pub fn identifier(&self) -> String {
-        format!("S{:02}E{:02}", self.season, self.episode)
+        if let Some(date) = self.date {
+            date.to_string()
+        }
+        else if self.last_episode > self.episode {
+            format!("S{:02}E{:02}-E{:02}", self.season, self.episode, self.last_episode)
+        }
+        else {
+            format!("S{:02}E{:02}", self.season, self.episode)
+        }
+    }
+    
+    /// Specials that don't have a number of their own yet.
+    /// These are numbered when added, after the others.
+    pub fn needs_number(&self) -> bool {
+        self.rule == "special" && self.episode == 0
+    }
+    
+    /// Whether the episode ranges of two files cover any of the same episodes.
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.season == other.season &&
+        self.episode <= other.last_episode &&
+        other.episode <= self.last_episode
     }
     
     /// The new file name for an episode, created from parsed parts.
     pub fn file_name(&self) -> String {
-        if self.name.is_empty() {
-            format!("{} {}.{}", 
-                self.show_name,
-                self.identifier(),
-                self.extension,
-            )
+        match self.date {
+            Some(_) => self.template.render_daily(self),
+            None => self.template.render(self),
         }
-        else {
-            format!("{} {} - {}.{}",
-                self.show_name,
-                self.identifier(),
-                self.name,
-                self.extension,
-            )
+    }
+    
+    /// The show folder within a library, like 'Show (2019)'.
+    pub fn show_folder(&self) -> String {
+        let show_name = self.show_name.replace(['/', '\\'], "-");
+        
+        match self.year {
+            Some(year) => format!("{} ({})", show_name, year),
+            None => show_name,
         }
     }
     
-    /// Rename the episode file.
-    pub fn rename(&self) -> io::Result<()> {
-        fs::rename(self.path.as_path(), self.path.with_file_name(self.file_name()))
+    /// The new path for an episode.
+    /// Without a library this is relative to the current folder.
+    /// Otherwise it's 'library/Show/Season 01/file', unless the template
+    /// has its own folders.
+    pub fn destination(&self) -> PathBuf {
+        match &self.library {
+            Some(library) if self.template.has_folders() => {
+                library.join(self.file_name())
+            },
+            Some(library) => {
+                library
+                    .join(self.show_folder())
+                    .join(format!("Season {:02}", self.season))
+                    .join(self.file_name())
+            },
+            None => {
+                self.path.parent()
+                    .unwrap_or(Path::new(""))
+                    .join(self.file_name())
+            },
+        }
     }
 }
 
 impl fmt::Display for Episode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Show the whole path when moving into a library.
+        let destination = match self.library {
+            Some(_) => self.destination().display().to_string(),
+            None => self.file_name(),
+        };
+        
         write!(f, "{:?} -> \"{}\"",
             self.path.file_name().unwrap(),
-            self.file_name(),
+            destination,
         )
     }
 }
@@ -62,6 +134,7 @@ impl hash::Hash for Episode {
     fn hash<H: hash::Hasher>(&self, state: &mut H) {
         self.season.hash(state);
         self.episode.hash(state);
+        self.last_episode.hash(state);
     }
 }
 
@@ -70,7 +143,8 @@ impl cmp::Eq for Episode {}
 impl cmp::PartialEq for Episode {
     fn eq(&self, other: &Self) -> bool {
         self.season == other.season &&
-        self.episode == other.episode
+        self.episode == other.episode &&
+        self.last_episode == other.last_episode
     }
 }
 
@@ -85,7 +159,7 @@ impl cmp::Ord for Episode {
         // @todo Would it better to just cmp() the identifier() ?
         if self.season == other.season {
             if self.episode == other.episode {
-                Ordering::Equal
+                self.last_episode.cmp(&other.last_episode)
             }
             else if self.episode > other.episode {
                 Ordering::Greater
@@ -109,13 +183,24 @@ mod test {
     use super::*;
     
     fn create_episode(episode: u32, season: u32) -> Episode {
+        create_multi_episode(episode, episode, season)
+    }
+    
+    fn create_multi_episode(episode: u32, last_episode: u32, season: u32) -> Episode {
         Episode {
             path: PathBuf::from("one/two/three.mp4"),
-            episode: episode,
-            season: season,
+            episode,
+            last_episode,
+            season,
             name: String::from("The One With The Baby Shower"),
             show_name: String::from("Friends"),
+            year: None,
             extension: String::from("mp4"),
+            template: Rc::new(Template::default()),
+            library: None,
+            rule: "S--E--",
+            date: None,
+            absolute: None,
         }
     }
     
@@ -129,9 +214,42 @@ mod test {
         assert_eq!(expected, actual);
     }
     
+    #[test]
+    fn test_episode_destination() {
+        let mut episode = create_episode(20, 8);
+        
+        let actual = episode.destination();
+        let expected = PathBuf::from("one/two/Friends S08E20 - The One With The Baby Shower.mp4");
+        assert_eq!(expected, actual);
+        
+        episode.template = Rc::new(Template::preset("kodi").unwrap());
+        
+        let actual = episode.destination();
+        let expected = PathBuf::from("one/two/Friends/Season 08/Friends S08E20 - The One With The Baby Shower.mp4");
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_episode_library() {
+        let mut episode = create_episode(20, 8);
+        episode.library = Some(PathBuf::from("/media/tv"));
+        episode.year = Some(1994);
+        
+        let actual = episode.destination();
+        let expected = PathBuf::from("/media/tv/Friends (1994)/Season 08/Friends (1994) S08E20 - The One With The Baby Shower.mp4");
+        assert_eq!(expected, actual);
+        
+        // Presets already have folders.
+        episode.template = Rc::new(Template::preset("kodi").unwrap());
+        
+        let actual = episode.destination();
+        let expected = PathBuf::from("/media/tv/Friends (1994)/Season 08/Friends S08E20 - The One With The Baby Shower.mp4");
+        assert_eq!(expected, actual);
+    }
+    
     #[test]
     fn test_episode_compare() {
-        let mut episodes = vec![
+        let mut episodes = [
             create_episode(20, 8),
             create_episode(10, 8),
             create_episode(30, 4),
@@ -143,4 +261,45 @@ mod test {
         assert_eq!(episodes[1].identifier(), "S08E10");
         assert_eq!(episodes[2].identifier(), "S08E20");
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_episode_multi_filename() {
+        let episode = create_multi_episode(5, 6, 2);
+        
+        let actual = episode.file_name();
+        let expected = "Friends S02E05-E06 - The One With The Baby Shower.mp4";
+        
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_episode_daily() {
+        let mut episode = create_episode(314, 2019);
+        episode.name = String::from("Guest Name");
+        episode.show_name = String::from("The Daily Show");
+        episode.date = Some(AirDate { year: 2019, month: 3, day: 14 });
+        
+        assert_eq!("2019-03-14", episode.identifier());
+        assert_eq!("The Daily Show - 2019-03-14 - Guest Name.mp4", episode.file_name());
+        
+        episode.template = Rc::new(Template::preset("plex").unwrap());
+        
+        let actual = episode.file_name();
+        let expected = "The Daily Show/Season 2019/The Daily Show - 2019-03-14 - Guest Name.mp4";
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_episode_multi_overlap() {
+        let multi = create_multi_episode(5, 6, 2);
+        
+        assert!(multi.overlaps(&create_episode(5, 2)));
+        assert!(multi.overlaps(&create_episode(6, 2)));
+        assert!(multi.overlaps(&create_multi_episode(6, 8, 2)));
+        assert!(!multi.overlaps(&create_episode(7, 2)));
+        assert!(!multi.overlaps(&create_episode(6, 3)));
+        
+        // Overlapping isn't the same episode.
+        assert!(multi != create_episode(5, 2));
+    }
+}
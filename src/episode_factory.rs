@@ -1,31 +1,62 @@
 
+use std::rc::Rc;
 use std::path::{Path, PathBuf};
 use std::collections::HashSet;
 
-use super::cleaner::Cleaner;
-use super::parsers::{parse_episode_name, parse_episode_number, parse_extension};
+use super::cleaner::{Cleaner, Scope};
+use super::parsers::{parse_absolute_number, parse_air_date, parse_episode_name, parse_episode_number_rule, parse_extension, parse_season_number, parse_show_year, parse_special};
 
-use crate::episode::Episode;
+use crate::episode::{AirDate, Episode};
+use crate::template::Template;
 
 /// Factory for creating episode objects.
 pub struct EpisodeFactory<'c> {
     season: u32,
     show_name: String,
+    year: Option<u32>,
     cleaner: &'c Cleaner,
+    template: Rc<Template>,
+    library: Option<PathBuf>,
+    /// Episodes in each season, to split up absolute numbers.
+    episodes_per_season: Vec<u32>,
     episodes: HashSet<Episode>,
 }
 
 impl<'c> EpisodeFactory<'c> {
     
+    /// A bracketed year in the show name, like 'Show (2019)', becomes
+    /// the {year} of the template.
     pub fn new<S: ToString>(show_name: S, season: u32, cleaner: &'c Cleaner) -> EpisodeFactory<'c> {
+        let (show_name, year) = parse_show_year(&show_name.to_string());
+        
         EpisodeFactory {
-            show_name: show_name.to_string(),
-            season: season,
-            cleaner: cleaner,
+            show_name,
+            year,
+            season,
+            cleaner,
+            template: Rc::new(Template::default()),
+            library: None,
+            episodes_per_season: Vec::new(),
             episodes: HashSet::new(),
         }
     }
     
+    /// Use a different output template for new episodes.
+    pub fn set_template(&mut self, template: Template) {
+        self.template = Rc::new(template);
+    }
+    
+    /// Move new episodes into a library folder, instead of renaming in place.
+    pub fn set_library<P: AsRef<Path>>(&mut self, library: P) {
+        self.library = Some(PathBuf::from(library.as_ref()));
+    }
+    
+    /// Split absolute numbers into seasons, given the episodes in each.
+    /// Without this they're numbered within the factory's season.
+    pub fn set_episodes_per_season(&mut self, counts: Vec<u32>) {
+        self.episodes_per_season = counts;
+    }
+    
     /// Create an episode.
     /// Parses the episode name, number and extension from the given path.
     pub fn create<P: AsRef<Path>>(&self, path: P) -> Result<Episode, String> {
@@ -37,10 +68,31 @@ impl<'c> EpisodeFactory<'c> {
             None => return Err(String::from("Cannot get file name.")),
         };
         
-        // Episode numbers must exist.
-        let episode_number = match parse_episode_number(&file_name) {
-            Some(num) => num,
-            None => return Err(String::from("Failed to parse episode number.")),
+        // Episode numbers or dates must exist.
+        let (episode_number, last_episode, rule, date) = match parse_episode_number_rule(&file_name) {
+            Some((first, last, rule)) => (first, last, rule, None),
+            None => match parse_air_date(&file_name) {
+                Some((year, month, day)) => {
+                    let date = AirDate { year, month, day };
+                    (month * 100 + day, month * 100 + day, "date", Some(date))
+                },
+                None => match parse_special(&file_name) {
+                    Some(number) => {
+                        let number = number.unwrap_or(0);
+                        (number, number, "special", None)
+                    },
+                    None => return Err(String::from("Failed to parse episode number.")),
+                },
+            },
+        };
+        
+        // Specials are season 0, unless there's a proper season.
+        let special = match rule {
+            "S--E--" | "-x-" => parse_season_number(&file_name) == Some(0),
+            "absolute" | "date" => false,
+            "special" => true,
+            // Special words are season 0, but like S--E-- a proper season wins.
+            _ => parse_season_number(self.after_show_name(&file_name)) == Some(0),
         };
         
         // Extensions must exist.
@@ -49,34 +101,97 @@ impl<'c> EpisodeFactory<'c> {
             None => return Err(String::from("Failed to parse file extension.")),
         };
         
+        // Find the season of an absolute number.
+        let absolute = parse_absolute_number(&file_name);
+        
+        let (season, episode_number, last_episode) = match absolute {
+            Some(absolute) if !self.episodes_per_season.is_empty() => {
+                let (season, episode) = split_absolute(absolute, &self.episodes_per_season)
+                    .ok_or(format!("Episode {} is past the last season.", absolute))?;
+                (season, episode, episode)
+            },
+            _ if special => (0, episode_number, last_episode),
+            _ => (date.map_or(self.season, |date| date.year), episode_number, last_episode),
+        };
+        
         // Episode names can be empty.
         let episode_name = match parse_episode_name(&file_name) {
-            Some(name) => self.cleaner.clean(&name),
+            Some(name) => self.cleaner.clean(&name, Scope::EpisodeName),
             None => String::new(),
         };
         
         Ok(Episode {
-            path: path,
-            season: self.season,
+            path,
+            season,
             show_name: self.show_name.clone(),
+            year: self.year,
             episode: episode_number,
-            extension: extension,
+            last_episode,
+            extension,
             name: episode_name,
+            template: self.template.clone(),
+            library: self.library.clone(),
+            rule,
+            date,
+            absolute,
         })
     }
     
-    /// Insert an episode.
+    /// The file name after the show name, if it starts with it.
+    /// So 'Law.and.Order.Special.Victims.Unit.Episode.5.mkv' isn't a special.
+    fn after_show_name<'f>(&self, file_name: &'f str) -> &'f str {
+        let mut rest = file_name;
+        
+        let words = self.show_name
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty());
+        
+        for word in words {
+            let start = match rest.find(char::is_alphanumeric) {
+                Some(start) => start,
+                None => return file_name,
+            };
+            
+            let end = rest[start..].find(|c: char| !c.is_alphanumeric())
+                .map_or(rest.len(), |end| start + end);
+            
+            if rest[start..end].to_lowercase() != word.to_lowercase() {
+                return file_name;
+            }
+            
+            rest = &rest[end..];
+        }
+        
+        rest
+    }
+    
+    /// Create and add an episode, for testing.
+    #[cfg(test)]
     pub fn insert<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
-        match self.create(path) {
-            Ok(episode) => {
-                if !self.episodes.insert(episode.clone()) {
-                    Err(format!("Duplicate episode {}", episode.identifier()))
-                }
-                else {
-                    Ok(())
-                }
-            },
-            Err(err) => Err(err),
+        self.create(path).and_then(|episode| self.add(episode))
+    }
+    
+    /// Add an episode that's already created.
+    /// Specials without a number are numbered after the others.
+    pub fn add(&mut self, mut episode: Episode) -> Result<(), String> {
+        if episode.needs_number() {
+            let last = self.episodes.iter()
+                .filter(|other| other.season == 0)
+                .map(|other| other.last_episode)
+                .max()
+                .unwrap_or(0);
+            
+            episode.episode = last + 1;
+            episode.last_episode = last + 1;
+        }
+        
+        // A multi-episode file clashes with any episode it covers.
+        if self.episodes.iter().any(|other| other.overlaps(&episode)) {
+            Err(format!("Duplicate episode {}", episode.identifier()))
+        }
+        else {
+            self.episodes.insert(episode);
+            Ok(())
         }
     }
     
@@ -88,6 +203,21 @@ impl<'c> EpisodeFactory<'c> {
     }
 }
 
+/// The (season, episode) of an absolute number.
+fn split_absolute(absolute: u32, episodes_per_season: &[u32]) -> Option<(u32, u32)> {
+    let mut episode = absolute;
+    
+    for (i, count) in episodes_per_season.iter().enumerate() {
+        if episode <= *count {
+            return Some((i as u32 + 1, episode));
+        }
+        
+        episode -= count;
+    }
+    
+    None
+}
+
 
 #[cfg(test)]
 mod test {
@@ -107,6 +237,139 @@ mod test {
         assert_eq!(expected, actual);
     }
     
+    #[test]
+    fn test_create_template() {
+        let cleaner = Cleaner::new(vec!["lol", "ftw"]);
+        let mut factory = EpisodeFactory::new("Friends", 1, &cleaner);
+        factory.set_template(Template::parse("{show} - {season}x{episode:02}[ - {title}].{ext}").unwrap());
+        
+        let episode = factory.create(PathBuf::from("friends.1x01.the.one.[ftw]-LOL.mp4"));
+        
+        let actual = episode.unwrap().file_name();
+        let expected = "Friends - 1x01 - The One.mp4";
+        
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_create_year() {
+        let cleaner = Cleaner::new(vec!["lol", "ftw"]);
+        let mut factory = EpisodeFactory::new("Friends (1994)", 1, &cleaner);
+        factory.set_template(Template::preset("plex").unwrap());
+        
+        let episode = factory.create(PathBuf::from("friends.1x01.the.one.[ftw]-LOL.mp4"));
+        
+        let actual = episode.unwrap().file_name();
+        let expected = "Friends (1994)/Season 01/Friends (1994) - S01E01 - The One.mp4";
+        
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_create_library() {
+        let cleaner = Cleaner::new(vec!["lol", "ftw"]);
+        let mut factory = EpisodeFactory::new("Friends", 1, &cleaner);
+        factory.set_library("/media/tv");
+        
+        let episode = factory.create(PathBuf::from("downloads/friends.1x01.the.one.[ftw]-LOL.mp4"));
+        
+        let actual = episode.unwrap().destination();
+        let expected = PathBuf::from("/media/tv/Friends/Season 01/Friends S01E01 - The One.mp4");
+        
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_create_daily() {
+        let cleaner = Cleaner::new(vec!["lol", "ftw"]);
+        let factory = EpisodeFactory::new("The Daily Show", 1, &cleaner);
+        
+        let episode = factory.create(PathBuf::from("The.Daily.Show.2019.03.14.Guest.Name.mkv")).unwrap();
+        
+        assert_eq!(2019, episode.season);
+        assert_eq!("date", episode.rule);
+        assert_eq!("The Daily Show - 2019-03-14 - Guest Name.mkv", episode.file_name());
+        
+        let episode = factory.create(PathBuf::from("the.daily.show.14-03-2019.lol.mkv")).unwrap();
+        assert_eq!("The Daily Show - 2019-03-14.mkv", episode.file_name());
+    }
+    
+    #[test]
+    fn test_create_absolute() {
+        let cleaner = Cleaner::new(vec!["lol", "ftw"]);
+        let mut factory = EpisodeFactory::new("Show Name", 1, &cleaner);
+        
+        let episode = factory.create(PathBuf::from("[SubGroup] Show Name - 137 [1080p][ABCD1234].mkv")).unwrap();
+        
+        assert_eq!(Some(137), episode.absolute);
+        assert_eq!("absolute", episode.rule);
+        assert_eq!("Show Name S01E137.mkv", episode.file_name());
+        
+        factory.set_episodes_per_season(vec![50, 51, 52]);
+        
+        let episode = factory.create(PathBuf::from("[SubGroup] Show Name - 137v2 [1080p][ABCD1234].mkv")).unwrap();
+        
+        assert_eq!(Some(137), episode.absolute);
+        assert_eq!("Show Name S03E36.mkv", episode.file_name());
+        
+        let actual = factory.create(PathBuf::from("[SubGroup] Show Name - 154 [1080p].mkv")).err();
+        assert_eq!(Some(String::from("Episode 154 is past the last season.")), actual);
+    }
+    
+    #[test]
+    fn test_split_absolute() {
+        let counts = vec![12, 13];
+        
+        assert_eq!(Some((1, 1)), split_absolute(1, &counts));
+        assert_eq!(Some((1, 12)), split_absolute(12, &counts));
+        assert_eq!(Some((2, 1)), split_absolute(13, &counts));
+        assert_eq!(Some((2, 13)), split_absolute(25, &counts));
+        assert_eq!(None, split_absolute(26, &counts));
+    }
+    
+    #[test]
+    fn test_create_special() {
+        let cleaner = Cleaner::new(vec!["lol", "ftw"]);
+        let factory = EpisodeFactory::new("Show", 2, &cleaner);
+        
+        let create = |path| factory.create(PathBuf::from(path)).unwrap().file_name();
+        
+        assert_eq!("Show S00E03 - Behind the Scenes.mkv", create("Show.S00E03.Behind.The.Scenes.mkv"));
+        assert_eq!("Show S00E01 - The Movie.mkv", create("Show.SP01.The.Movie.mkv"));
+        assert_eq!("Show S00E02.mkv", create("Show.OVA.2.mkv"));
+        assert_eq!("Show S00E04.mkv", create("Show.Special.Part.4.mkv"));
+        
+        // Just a title.
+        assert_eq!("Show S02E05 - Christmas Special.mkv", create("Show.S02E05.Christmas.Special.mkv"));
+        assert_eq!("Show S00E01 - Special.mkv", create("Show.1of2.Special.mkv"));
+        assert_eq!("Show S02E03 - Christmas Special.mkv", create("Show.Series.2.3of4.Christmas.Special.mkv"));
+        
+        // Or part of the show name.
+        let factory = EpisodeFactory::new("Law and Order Special Victims Unit", 3, &cleaner);
+        let episode = factory.create(PathBuf::from("Law.and.Order.Special.Victims.Unit.Episode.5.mkv")).unwrap();
+        
+        assert_eq!("name", episode.rule);
+        assert_eq!("S03E05", episode.identifier());
+    }
+    
+    #[test]
+    fn test_insert_special() {
+        let cleaner = Cleaner::new(vec!["lol", "ftw"]);
+        let mut factory = EpisodeFactory::new("Show", 1, &cleaner);
+        
+        factory.insert(PathBuf::from("Show.S01E01.mkv")).unwrap();
+        factory.insert(PathBuf::from("Show.SP02.mkv")).unwrap();
+        factory.insert(PathBuf::from("Show.Christmas.Special.mkv")).unwrap();
+        factory.insert(PathBuf::from("Show.OVA.mkv")).unwrap();
+        
+        let sorted = factory.get_all();
+        
+        assert_eq!(sorted[0].identifier(), "S00E02");
+        assert_eq!(sorted[1].identifier(), "S00E03");
+        assert_eq!(sorted[2].identifier(), "S00E04");
+        assert_eq!(sorted[3].identifier(), "S01E01");
+    }
+    
     #[test]
     fn test_insert() {
         let cleaner = Cleaner::new(vec!["lol", "ftw"]);
@@ -122,4 +385,22 @@ mod test {
         assert_eq!(sorted[1].identifier(), "S01E02");
         assert_eq!(sorted[2].identifier(), "S01E03");
     }
+    
+    #[test]
+    fn test_insert_multi() {
+        let cleaner = Cleaner::new(vec!["lol", "ftw"]);
+        let mut factory = EpisodeFactory::new("friends", 2, &cleaner);
+        
+        factory.insert(PathBuf::from("friends.S02E05E06.mp4")).unwrap();
+        factory.insert(PathBuf::from("friends.S02E07.mp4")).unwrap();
+        
+        // The second half of the double episode is a duplicate.
+        let actual = factory.insert(PathBuf::from("friends.S02E06.mp4"));
+        assert_eq!(actual, Err(String::from("Duplicate episode S02E06")));
+        
+        let sorted = factory.get_all();
+        
+        assert_eq!(sorted[0].identifier(), "S02E05-E06");
+        assert_eq!(sorted[1].identifier(), "S02E07");
+    }
 }
@@ -3,7 +3,7 @@ use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::io::{self, Write};
 
-use directories::ProjectDirs;
+use crate::config::get_config_path;
 
 /// Default in-built exclude rules.
 pub const EXCLUDE_RULES: [&'static str; 26] = [
@@ -35,38 +35,41 @@ pub const EXCLUDE_RULES: [&'static str; 26] = [
     "hd",
 ];
 
+/// Help text written above the rules in a fresh config file.
+const RULES_HELP: &str = "\
+# Exclude rules, one per line.
+#
+# Rules remove whole words. Write 'sub:' in front to remove text inside
+# words too, 're:' for a regex, or 'glob:' for a wildcard like glob:*rip
+#
+# Replace instead of removing with 'from => to', like: & => and
+# Rules run in order. Replacements with capitals are kept as written.
+#
+# Rules apply to all names, unless they're after a [show-name] or
+# [episode-name] section. Go back to all names with [all].
+";
+
 /// Write the default exclude list to file.
 pub fn write_rules<P: AsRef<Path>>(path: P) -> Result<(), io::Error> {
-    match File::create(path) {
-        Ok(file) => {
-            for rule in &EXCLUDE_RULES {
-                if let Err(err) = write!(&file, "{}\n", rule) {
-                    return Err(err);
-                }
-            }
-            Ok(())
-        },
-        Err(err) => Err(err),
+    let mut file = File::create(path)?;
+    
+    writeln!(file, "{}", RULES_HELP)?;
+    
+    for rule in &EXCLUDE_RULES {
+        writeln!(file, "{}", rule)?;
     }
+    
+    Ok(())
 }
 
 /// Get the config path.
 pub fn get_rules_path() -> Result<PathBuf, &'static str> {
-    // @todo Should these be const somewhere?
-    match ProjectDirs::from("com", "gwillz", "tv-rename") {
-        Some(dirs) => {
-            let path = dirs.config_dir().with_file_name("exclude.txt");
-            
-            // Write a fresh file if it doesn't already exist.
-            if !path.exists() {
-                if write_rules(&path).is_err() {
-                    return Err("Failed to write config file.");
-                }
-            }
-            
-            return Ok(path);
-        },
-        // Rare? I assume?
-        None => Err("Failed to find config.")
+    let path = get_config_path("exclude.txt")?;
+    
+    // Write a fresh file if it doesn't already exist.
+    if !path.exists() && write_rules(&path).is_err() {
+        return Err("Failed to write config file.");
     }
+    
+    Ok(path)
 }
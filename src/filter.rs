@@ -0,0 +1,242 @@
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::config::get_config_path;
+
+/// Default video extensions.
+pub const VIDEO_EXTENSIONS: [&str; 14] = [
+    "mkv",
+    "mp4",
+    "m4v",
+    "avi",
+    "mov",
+    "wmv",
+    "mpg",
+    "mpeg",
+    "ts",
+    "m2ts",
+    "webm",
+    "flv",
+    "ogv",
+    "divx",
+];
+
+lazy_static! {
+    // A sample clip, like 'show.s01e01.sample.mkv' or 'sample-show.mkv'.
+    static ref SAMPLE: Regex =
+        Regex::new(r"(?i)(?:^|[^a-z])samples?(?:$|[^a-z])").unwrap();
+}
+
+/// Why a file was left alone.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Skip {
+    Folder,
+    /// Dot files, like '.DS_Store'.
+    Hidden,
+    Sample,
+    /// The extension isn't in the list, or there isn't one.
+    NotVideo(Option<String>),
+    /// The episode couldn't be understood.
+    Unknown(String),
+    /// Subtitles or such, without a video.
+    Alone,
+    /// Taken out of the preview.
+    Excluded,
+}
+
+impl Skip {
+    /// A short name for counting up skips of the same kind.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Skip::Folder => "folder",
+            Skip::Hidden => "hidden",
+            Skip::Sample => "sample",
+            Skip::NotVideo(_) => "not a video",
+            Skip::Unknown(_) => "not understood",
+            Skip::Alone => "no video",
+            Skip::Excluded => "excluded",
+        }
+    }
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Skip::Folder => write!(f, "It's a folder."),
+            Skip::Hidden => write!(f, "It's hidden."),
+            Skip::Sample => write!(f, "It's a sample."),
+            Skip::NotVideo(Some(ext)) => write!(f, "Not a video (.{}).", ext),
+            Skip::NotVideo(None) => write!(f, "Not a video (no extension)."),
+            Skip::Unknown(err) => write!(f, "{}", err),
+            Skip::Alone => write!(f, "No video to go with it."),
+            Skip::Excluded => write!(f, "Left out."),
+        }
+    }
+}
+
+/// Decides which files in a folder are episodes.
+pub struct Filter {
+    extensions: HashSet<String>,
+}
+
+impl Filter {
+    /// For testing.
+    #[allow(dead_code)]
+    pub fn new<S: ToString>(extensions: Vec<S>) -> Filter {
+        Filter {
+            extensions: extensions.iter().map(|ext| ext.to_string()).collect(),
+        }
+    }
+    
+    /// Load a list of extensions, one per line.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Filter, io::Error> {
+        fs::read_to_string(path).map(|contents| {
+            Filter {
+                extensions: parse_extensions(contents),
+            }
+        })
+    }
+    
+    pub fn size(&self) -> usize {
+        self.extensions.len()
+    }
+    
+    /// Whether to skip a file, and why.
+    pub fn check<P: AsRef<Path>>(&self, path: P) -> Result<(), Skip> {
+        let path = path.as_ref();
+        
+        if path.is_dir() {
+            return Err(Skip::Folder);
+        }
+        
+        let file_name = path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        
+        if file_name.starts_with('.') {
+            return Err(Skip::Hidden);
+        }
+        
+        let extension = path.extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        
+        match extension {
+            Some(ref ext) if self.extensions.contains(ext) => (),
+            _ => return Err(Skip::NotVideo(extension)),
+        }
+        
+        // Either the file is called 'sample', or it's in a 'Sample' folder.
+        let stem = path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        
+        let folder = path.parent()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        
+        if SAMPLE.is_match(&stem) || folder == "sample" || folder == "samples" {
+            return Err(Skip::Sample);
+        }
+        
+        Ok(())
+    }
+}
+
+/// Count up why files were skipped, like '2 not a video, 1 sample'.
+pub fn summary(skipped: &[(PathBuf, Skip)]) -> String {
+    let mut kinds: Vec<(&'static str, usize)> = Vec::new();
+    
+    for (_, skip) in skipped {
+        match kinds.iter_mut().find(|(kind, _)| *kind == skip.kind()) {
+            Some((_, count)) => *count += 1,
+            None => kinds.push((skip.kind(), 1)),
+        }
+    }
+    
+    kinds.iter()
+        .map(|(kind, count)| format!("{} {}", count, kind))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Extensions are lowercase, without the dot.
+fn parse_extensions<S: AsRef<str>>(contents: S) -> HashSet<String> {
+    contents.as_ref().to_lowercase().split("\n")
+        .map(|s| s.trim().trim_start_matches('.'))
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Write the default extension list to file.
+pub fn write_extensions<P: AsRef<Path>>(path: P) -> Result<(), io::Error> {
+    let mut file = File::create(path)?;
+    
+    for ext in &VIDEO_EXTENSIONS {
+        writeln!(file, "{}", ext)?;
+    }
+    
+    Ok(())
+}
+
+/// Get the extensions path.
+pub fn get_extensions_path() -> Result<PathBuf, &'static str> {
+    let path = get_config_path("extensions.txt")?;
+    
+    // Write a fresh file if it doesn't already exist.
+    if !path.exists() && write_extensions(&path).is_err() {
+        return Err("Failed to write extensions file.");
+    }
+    
+    Ok(path)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    
+    #[test]
+    fn test_check() {
+        let filter = Filter::new(vec!["mkv", "mp4"]);
+        
+        assert_eq!(Ok(()), filter.check("tv/show.s01e01.mkv"));
+        assert_eq!(Ok(()), filter.check("tv/show.s01e01.MP4"));
+        assert_eq!(Ok(()), filter.check("tv/samuel.s01e01.mkv"));
+        assert_eq!(Err(Skip::NotVideo(Some(String::from("nfo")))), filter.check("tv/show.s01e01.nfo"));
+        assert_eq!(Err(Skip::NotVideo(None)), filter.check("tv/README"));
+        assert_eq!(Err(Skip::Hidden), filter.check("tv/.show.s01e01.mkv"));
+        assert_eq!(Err(Skip::Sample), filter.check("tv/show.s01e01.sample.mkv"));
+        assert_eq!(Err(Skip::Sample), filter.check("tv/sample-show.s01e01.mkv"));
+        assert_eq!(Err(Skip::Sample), filter.check("tv/Sample/show.s01e01.mkv"));
+        assert_eq!(Err(Skip::Folder), filter.check("test"));
+    }
+    
+    #[test]
+    fn test_load() {
+        let filter = Filter::load(Path::new("test/extensions.txt")).unwrap();
+        
+        assert_eq!(3, filter.size());
+        assert_eq!(Ok(()), filter.check("show.s01e01.MKV"));
+        assert_eq!(Ok(()), filter.check("show.s01e01.ts"));
+    }
+    
+    #[test]
+    fn test_summary() {
+        let skipped = vec![
+            (PathBuf::from("one.nfo"), Skip::NotVideo(Some(String::from("nfo")))),
+            (PathBuf::from("sample.mkv"), Skip::Sample),
+            (PathBuf::from("two.txt"), Skip::NotVideo(Some(String::from("txt")))),
+        ];
+        
+        assert_eq!("2 not a video, 1 sample", summary(&skipped));
+    }
+}
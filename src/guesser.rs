@@ -1,8 +1,8 @@
 
-use std::fs::DirEntry;
+use std::path::Path;
 use std::collections::HashMap;
 use std::hash::Hash;
-use std::cmp::Eq;
+use std::cmp::{Eq, Reverse};
 
 use super::parsers::{parse_show_name, parse_season_number};
 
@@ -16,10 +16,11 @@ pub struct Guesser {
 
 impl Guesser {
     
-    pub fn new(files: &Vec<DirEntry>) -> Guesser {
+    pub fn new<P: AsRef<Path>>(files: &[P]) -> Guesser {
         // Get just the file names.
         let file_names = files.iter()
-            .map(|entry| String::from(entry.file_name().to_str().unwrap()))
+            .filter_map(|path| path.as_ref().file_name())
+            .map(|name| name.to_string_lossy().to_string())
             .collect();
         
         Guesser {
@@ -37,40 +38,50 @@ impl Guesser {
         self.guess(parse_season_number)
     }
     
+    /// Get the show name, if no other name is as likely.
+    pub fn get_certain_show_name(&self) -> Option<String> {
+        self.guess_certain(parse_show_name)
+    }
+    
+    /// Get the season number, if no other season is as likely.
+    pub fn get_certain_season_number(&self) -> Option<u32> {
+        self.guess_certain(parse_season_number)
+    }
+    
     /// Internal guesser loop.
     fn guess<R: Hash + Eq>(&self, parser: Parser<R>) -> Option<R> {
-        let mut guesses: HashMap<R, u32> = HashMap::new();
+        self.count(parser).into_iter()
+            .next()
+            .map(|(guess, _)| guess)
+    }
     
-        // Gather up all the possible values.
-        for path in &self.files {
-            let res = parser(path.as_ref());
-            
-            if res.is_some() {
-                let key = res.unwrap();
-                
-                match guesses.get(&key) {
-                    Some(t) => guesses.insert(key, t + 1),
-                    None => guesses.insert(key, 1),
-                };
-            }
-        }
+    /// Like guess, but nothing if there's a tie.
+    fn guess_certain<R: Hash + Eq>(&self, parser: Parser<R>) -> Option<R> {
+        let mut guesses = self.count(parser).into_iter();
         
-        let mut largest = 0;
-        let mut found: Option<R> = None;
+        match (guesses.next(), guesses.next()) {
+            (Some((_, first)), Some((_, second))) if first == second => None,
+            (Some((guess, _)), _) => Some(guess),
+            _ => None,
+        }
+    }
+    
+    /// Count all the possible values, most common first.
+    fn count<R: Hash + Eq>(&self, parser: Parser<R>) -> Vec<(R, u32)> {
+        let mut guesses: HashMap<R, u32> = HashMap::new();
         
-        // Find the most common and return that.
-        for (guess, count) in guesses {
-            if count > largest {
-                largest = count;
-                found = Some(guess);
+        for path in &self.files {
+            if let Some(key) = parser(path.as_ref()) {
+                *guesses.entry(key).or_insert(0) += 1;
             }
         }
         
-        return found;
+        let mut guesses: Vec<(R, u32)> = guesses.into_iter().collect();
+        guesses.sort_by_key(|guess| Reverse(guess.1));
+        guesses
     }
 }
 
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -114,4 +125,34 @@ mod test {
         assert_eq!(guesser.get_season_number(), Some(1));
         assert_eq!(guesser.get_show_name(), None);
     }
+    
+    #[test]
+    fn test_certain() {
+        let guesser = Guesser {
+            files: vec![
+                String::from("three.s03e01.mp4"),
+                String::from("three.s03e02.mp4"),
+                String::from("four.s04e03.mp4"),
+                String::from("four.s04e04.mp4"),
+                String::from("four.s04e05.mp4"),
+            ]
+        };
+        
+        assert_eq!(guesser.get_certain_show_name(), Some(String::from("four.")));
+        assert_eq!(guesser.get_certain_season_number(), Some(4));
+    }
+    
+    #[test]
+    fn test_uncertain() {
+        let guesser = Guesser {
+            files: vec![
+                String::from("three.s03e01.mp4"),
+                String::from("four.s04e01.mp4"),
+            ]
+        };
+        
+        assert_eq!(guesser.get_certain_show_name(), None);
+        assert_eq!(guesser.get_certain_season_number(), None);
+        assert!(guesser.get_show_name().is_some());
+    }
 }
@@ -85,6 +85,16 @@ impl Input {
         }
     }
     
+    /// Query for a line of anything, maybe empty.
+    pub fn line(&mut self, prompt: &str) -> String {
+        loop {
+            match self.rl.readline(prompt) {
+                Ok(line) => return String::from(line.trim()),
+                Err(err) => (self.handler)(err),
+            }
+        }
+    }
+    
     /// Wait for input. Enter or an escape command - don't care.
     pub fn pause(&mut self) {
         println!("\nPress enter to exit.");
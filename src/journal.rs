@@ -0,0 +1,436 @@
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::get_data_path;
+use crate::transfer::{Mode, Transfer, Verify, links_to};
+
+/// A record of one finished file operation.
+/// The size and modified time show if the new file has changed since.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub mode: Mode,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    size: u64,
+    modified: u128,
+}
+
+impl Entry {
+    /// Create an entry for an operation that has just happened.
+    pub fn new(mode: Mode, from: &Path, to: &Path) -> io::Result<Entry> {
+        let (size, modified) = stamp(to)?;
+        
+        Ok(Entry {
+            mode,
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+            size,
+            modified,
+        })
+    }
+    
+    /// Parse a line written by `to_line`.
+    fn parse(line: &str) -> Option<Entry> {
+        let line: Line = serde_json::from_str(line).ok()?;
+        
+        Some(Entry {
+            mode: Mode::parse(&line.mode)?,
+            from: line.from,
+            to: line.to,
+            size: line.size,
+            modified: line.modified,
+        })
+    }
+    
+    /// One JSON object, so any path can be written except those that
+    /// aren't UTF-8.
+    fn to_line(&self) -> io::Result<String> {
+        let line = Line {
+            mode: String::from(self.mode.name()),
+            from: self.from.clone(),
+            to: self.to.clone(),
+            size: self.size,
+            modified: self.modified,
+        };
+        
+        serde_json::to_string(&line)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+    
+    /// Put things back the way they were.
+    /// This refuses if the new file has changed or the old path is taken.
+    /// A copy or link is only removed while the original is still there.
+    pub fn undo(&self) -> Result<(), String> {
+        match stamp(&self.to) {
+            Ok(stamp) => {
+                if stamp != (self.size, self.modified) {
+                    return Err(format!("{} has changed since.", self.to.display()));
+                }
+            },
+            Err(_) => {
+                return Err(format!("{} is missing.", self.to.display()));
+            },
+        }
+        
+        match self.mode {
+            Mode::Rename if self.from.exists() => {
+                return Err(format!("{} already exists.", self.from.display()));
+            },
+            Mode::Rename => (),
+            _ if !self.from.exists() => {
+                return Err(format!("{} is missing.", self.from.display()));
+            },
+            Mode::Symlink | Mode::RelativeSymlink if !links_to(&self.to, &self.from) => {
+                return Err(format!("{} doesn't link to {} anymore.", self.to.display(), self.from.display()));
+            },
+            _ => (),
+        }
+        
+        let result = self.from.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| Transfer::new(self.mode, Verify::Size, no_progress).revert(&self.from, &self.to));
+        
+        result.map_err(|err| format!("{}: {}", self.to.display(), err))
+    }
+}
+
+/// An entry as it's written to the journal, one per line.
+#[derive(Serialize, Deserialize)]
+struct Line {
+    mode: String,
+    from: PathBuf,
+    to: PathBuf,
+    size: u64,
+    modified: u128,
+}
+
+/// Journal of file operations, so a run can be undone.
+/// There is one file per run, named by the time it started.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+impl Journal {
+    /// Start a new journal in a folder.
+    pub fn create<P: AsRef<Path>>(dir: P) -> io::Result<Journal> {
+        fs::create_dir_all(&dir)?;
+        
+        let mut time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis())
+            .unwrap_or(0);
+        
+        // Never clobber another journal.
+        loop {
+            let path = dir.as_ref().join(format!("{}.journal", time));
+            
+            match OpenOptions::new().append(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    return Ok(Journal {
+                        path,
+                        file,
+                    });
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    time += 1;
+                },
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    /// Throw away the journal, for when everything was rolled back.
+    pub fn discard(self) -> io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+    
+    /// Record a finished operation.
+    /// This is written immediately, in case something breaks later.
+    pub fn record(&mut self, mode: Mode, from: &Path, to: &Path) -> io::Result<()> {
+        let entry = Entry::new(mode, from, to)?;
+        writeln!(self.file, "{}", entry.to_line()?)?;
+        self.file.sync_data()
+    }
+}
+
+/// Read the entries of a journal, in the order they happened.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<Entry>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read journal: {}", err))?;
+    
+    contents.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| {
+            Entry::parse(line)
+                .ok_or(format!("line {}: Bad journal entry.", i + 1))
+        })
+        .collect()
+}
+
+/// Finish with a journal after an undo.
+/// Anything that couldn't be undone is kept for another try.
+pub fn finish<P: AsRef<Path>>(path: P, remaining: &[Entry]) -> io::Result<()> {
+    let path = path.as_ref();
+    
+    if remaining.is_empty() {
+        return fs::rename(path, path.with_extension("undone"));
+    }
+    
+    let mut file = File::create(path)?;
+    
+    for entry in remaining {
+        writeln!(file, "{}", entry.to_line()?)?;
+    }
+    
+    Ok(())
+}
+
+/// List the journals that can be undone, oldest first.
+pub fn list<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        // Nothing has happened yet.
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    
+    let mut journals = Vec::new();
+    
+    for entry in entries {
+        let path = entry?.path();
+        
+        if path.extension().is_some_and(|ext| ext == "journal") {
+            journals.push(path);
+        }
+    }
+    
+    journals.sort();
+    Ok(journals)
+}
+
+/// Get the journal folder.
+pub fn get_journal_dir() -> Result<PathBuf, &'static str> {
+    get_data_path("journals")
+}
+
+/// Undo is quiet about copy progress.
+fn no_progress(_: u64, _: u64) {}
+
+/// The size and modified time of a file (or the link itself).
+fn stamp(path: &Path) -> io::Result<(u64, u128)> {
+    let metadata = fs::symlink_metadata(path)?;
+    
+    let modified = metadata.modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos())
+        .unwrap_or(0);
+    
+    Ok((metadata.len(), modified))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    
+    fn create_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("tv-rename-journal-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    #[test]
+    fn test_record() {
+        let dir = create_dir("record");
+        let from = dir.join("one.mp4");
+        let to = dir.join("two.mp4");
+        fs::write(&to, "episode").unwrap();
+        
+        let mut journal = Journal::create(dir.join("journals")).unwrap();
+        journal.record(Mode::Rename, &from, &to).unwrap();
+        journal.record(Mode::Copy, &from, &to).unwrap();
+        
+        let entries = read(journal.path()).unwrap();
+        
+        assert_eq!(2, entries.len());
+        assert_eq!(Mode::Rename, entries[0].mode);
+        assert_eq!(Mode::Copy, entries[1].mode);
+        assert_eq!(from, entries[0].from);
+        assert_eq!(to, entries[0].to);
+        assert_eq!(7, entries[0].size);
+    }
+    
+    #[test]
+    fn test_record_paths() {
+        let dir = create_dir("record-paths");
+        let from = dir.join("one\ttab\nline \"quoted\".mp4");
+        let to = dir.join("two.mp4");
+        fs::write(&to, "episode").unwrap();
+        
+        let mut journal = Journal::create(dir.join("journals")).unwrap();
+        journal.record(Mode::Rename, &from, &to).unwrap();
+        
+        let entries = read(journal.path()).unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(from, entries[0].from);
+    }
+    
+    #[test]
+    #[cfg(unix)]
+    fn test_record_not_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        
+        let dir = create_dir("record-not-utf8");
+        let from = dir.join("one.mp4");
+        let to = dir.join(OsStr::from_bytes(b"two\xff.mp4"));
+        fs::write(&to, "episode").unwrap();
+        
+        // It couldn't be found again, so it's refused.
+        let mut journal = Journal::create(dir.join("journals")).unwrap();
+        let err = journal.record(Mode::Rename, &from, &to).unwrap_err();
+        
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+    
+    #[test]
+    fn test_list() {
+        let dir = create_dir("list");
+        
+        let one = Journal::create(&dir).unwrap();
+        let two = Journal::create(&dir).unwrap();
+        fs::write(dir.join("other.txt"), "").unwrap();
+        
+        let actual = list(&dir).unwrap();
+        let expected = vec![one.path().to_path_buf(), two.path().to_path_buf()];
+        
+        assert_eq!(expected, actual);
+        assert_eq!(0, list(dir.join("nope")).unwrap().len());
+    }
+    
+    #[test]
+    fn test_undo() {
+        let dir = create_dir("undo");
+        let from = dir.join("one.mp4");
+        let to = dir.join("show/two.mp4");
+        fs::create_dir_all(dir.join("show")).unwrap();
+        fs::write(&to, "episode").unwrap();
+        
+        let entry = Entry::new(Mode::Rename, &from, &to).unwrap();
+        entry.undo().unwrap();
+        
+        assert!(!to.exists());
+        assert_eq!("episode", fs::read_to_string(&from).unwrap());
+    }
+    
+    #[test]
+    fn test_undo_copy() {
+        let dir = create_dir("undo-copy");
+        let from = dir.join("one.mp4");
+        let to = dir.join("two.mp4");
+        fs::write(&from, "episode").unwrap();
+        fs::write(&to, "episode").unwrap();
+        
+        let entry = Entry::new(Mode::Copy, &from, &to).unwrap();
+        entry.undo().unwrap();
+        
+        assert!(from.exists());
+        assert!(!to.exists());
+    }
+    
+    #[test]
+    fn test_undo_refused() {
+        let dir = create_dir("undo-refused");
+        let from = dir.join("one.mp4");
+        let to = dir.join("two.mp4");
+        fs::write(&to, "episode").unwrap();
+        
+        let entry = Entry::new(Mode::Rename, &from, &to).unwrap();
+        
+        // Something else took the old name.
+        fs::write(&from, "something else").unwrap();
+        assert!(entry.undo().unwrap_err().ends_with("already exists."));
+        
+        // The new file was changed.
+        fs::remove_file(&from).unwrap();
+        fs::write(&to, "a different episode").unwrap();
+        assert!(entry.undo().unwrap_err().ends_with("has changed since."));
+        
+        assert!(!from.exists());
+        assert!(to.exists());
+    }
+    
+    #[test]
+    fn test_undo_copy_refused() {
+        let dir = create_dir("undo-copy-refused");
+        let from = dir.join("one.mp4");
+        let to = dir.join("two.mp4");
+        fs::write(&to, "episode").unwrap();
+        
+        // The original was deleted, this is the only one left.
+        for mode in [Mode::Copy, Mode::Hardlink] {
+            let entry = Entry::new(mode, &from, &to).unwrap();
+            assert!(entry.undo().unwrap_err().ends_with("one.mp4 is missing."));
+            assert!(to.exists());
+        }
+    }
+    
+    #[test]
+    #[cfg(unix)]
+    fn test_undo_symlink() {
+        let dir = create_dir("undo-symlink").canonicalize().unwrap();
+        let from = dir.join("one.mp4");
+        let other = dir.join("other.mp4");
+        let to = dir.join("show/two.mp4");
+        fs::create_dir_all(dir.join("show")).unwrap();
+        fs::write(&from, "episode").unwrap();
+        fs::write(&other, "another episode").unwrap();
+        
+        // Pointing somewhere else.
+        std::os::unix::fs::symlink(&other, &to).unwrap();
+        let entry = Entry::new(Mode::Symlink, &from, &to).unwrap();
+        assert!(entry.undo().unwrap_err().ends_with("one.mp4 anymore."));
+        
+        fs::remove_file(&to).unwrap();
+        std::os::unix::fs::symlink("../one.mp4", &to).unwrap();
+        let entry = Entry::new(Mode::RelativeSymlink, &from, &to).unwrap();
+        entry.undo().unwrap();
+        
+        assert!(from.exists());
+        assert!(fs::symlink_metadata(&to).is_err());
+    }
+    
+    #[test]
+    fn test_finish() {
+        let dir = create_dir("finish");
+        let to = dir.join("two.mp4");
+        fs::write(&to, "episode").unwrap();
+        
+        let mut journal = Journal::create(&dir).unwrap();
+        journal.record(Mode::Copy, &dir.join("one.mp4"), &to).unwrap();
+        journal.record(Mode::Copy, &dir.join("three.mp4"), &to).unwrap();
+        
+        // Keep the ones that failed.
+        let entries = read(journal.path()).unwrap();
+        finish(journal.path(), &entries[1..]).unwrap();
+        assert_eq!(entries[1..].to_vec(), read(journal.path()).unwrap());
+        
+        // All done.
+        finish(journal.path(), &[]).unwrap();
+        assert!(!journal.path().exists());
+        assert!(journal.path().with_extension("undone").exists());
+    }
+}
@@ -1,124 +1,590 @@
 
 use std::env;
-use std::fs::{self, DirEntry};
-use std::io;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use rustyline::error::ReadlineError;
 
 use input::Input;
-use cleaner::Cleaner;
+use cleaner::{Cleaner, Scope};
 use guesser::Guesser;
+use episode::Episode;
 use episode_factory::EpisodeFactory;
 use exclude_rules::get_rules_path;
+use casing::get_acronyms_path;
+use filter::{Filter, Skip, get_extensions_path, summary};
+use template::{Template, get_template_path};
+use transfer::Transfer;
+use journal::{Journal, get_journal_dir};
+use batch::{Batch, Step};
+use conflict::{Policy, resolve_with_sidecars};
+use args::{Args, Command, Format, USAGE};
+use plan::Plan;
+use sidecar::{Sidecar, is_sidecar};
+use review::{Answer, Review, ask};
 
 mod input;
 mod parsers;
 mod cleaner;
+mod casing;
 mod guesser;
 mod episode;
 mod episode_factory;
 mod exclude_rules;
+mod filter;
+mod config;
+mod template;
+mod transfer;
+mod journal;
+mod batch;
+mod planner;
+mod conflict;
+mod args;
+mod plan;
+mod sidecar;
+mod review;
+
+/// When stdout is for another program, messages go to stderr instead.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Print a message for people.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if QUIET.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        }
+        else {
+            println!($($arg)*);
+        }
+    };
+}
 
 fn main() {
-    println!("TV Rename v1");
-    println!("------------");
+    let args = Args::parse(env::args().skip(1))
+        .unwrap_or_else(|e| quit(&format!("{}\n\n{}", e, USAGE)));
+    
+    // Nobody is there to answer questions when the output is for a program.
+    let interactive = !args.yes && args.format == Format::Text;
+    QUIET.store(args.format == Format::Json, Ordering::Relaxed);
+    
+    say!("TV Rename v1");
+    say!("------------");
+    
+    match args.command {
+        Command::Help => {
+            println!("{}", USAGE);
+            std::process::exit(0);
+        },
+        Command::Undo(ref name) => undo(name.as_ref(), args.yes),
+        Command::Apply(ref path) => apply(path, &args, interactive),
+        Command::Rename => (),
+    }
     
     let rules_path = get_rules_path()
         .unwrap_or_else(|e| quit(e));
     
-    let cleaner = Cleaner::load(rules_path)
-        .unwrap_or_else(|_| quit("Failed to load config file."));
+    let acronyms_path = get_acronyms_path()
+        .unwrap_or_else(|e| quit(e));
+    
+    let mut cleaner = Cleaner::load(&rules_path, &acronyms_path)
+        .unwrap_or_else(|e| quit(&e));
     
     let mut input = Input::new(input_errors);
     
-    println!("Exclude DB loaded {} rules.", cleaner.size());
+    say!("Exclude DB loaded {} rules.", cleaner.size());
+    say!("Acronym list loaded {} words.", cleaner.acronyms());
+    
+    let extensions_path = get_extensions_path()
+        .unwrap_or_else(|e| quit(e));
+    
+    let filter = Filter::load(extensions_path)
+        .unwrap_or_else(|_| quit("Failed to load extensions file."));
+    
+    say!("Video list loaded {} extensions.", filter.size());
+    
+    // Rename, copy or link.
+    let transfer = Transfer::new(args.mode, args.verify, print_progress);
+    
+    // Check the template before doing anything else.
+    let template_path = get_template_path()
+        .unwrap_or_else(|e| quit(e));
+    
+    // A preset from the command line wins over the config file.
+    let template = match args.preset {
+        Some(ref name) => Template::preset(name)
+            .unwrap_or_else(|e| quit(&e)),
+        None => Template::load(&template_path)
+            .unwrap_or_else(|e| quit(&format!("Bad template in {}\n{}", template_path.display(), e))),
+    };
     
     // Get target path.
-    let path = get_directory()
+    let path = get_directory(args.path.as_ref())
         .unwrap_or_else(|_| quit("Can't find that path!"));
     
-    println!("Reading {}", path.display());
+    say!("Reading {}", path.display());
+    
+    // Read target directory, or every folder inside it.
+    let groups = if args.recursive { walk_directory(&path) }
+        else { read_directory(&path).map(|files| vec![(path.clone(), files)]) };
     
-    // Read target directory.
-    let files = read_directory(&path)
+    let groups = groups
         .unwrap_or_else(|_| quit("Can't read the directory!"));
     
-    println!("Loaded {} files.", files.len());
-    println!("");
+    say!("Loaded {} files.", groups.iter().map(|(_, files)| files.len()).sum::<usize>());
+    say!("");
     
-    for file in &files {
-        println!("{:?}", file.file_name());
+    for (dir, files) in &groups {
+        if args.recursive {
+            say!("{}", dir.display());
+        }
+        
+        for file in files {
+            say!("{:?}", file.file_name().unwrap_or_default());
+        }
     }
     
-    println!("");
-    
-    // The guesser object finds the most likely show/season.
-    let guesser = Guesser::new(&files);
+    say!("");
     
-    // Guess show name.
-    let show_name = guesser.get_show_name()
-        .map(|name| cleaner.clean(&name));
+    // Leave out anything that isn't an episode, and say why.
+    // Subtitles and such are kept aside to follow their video.
+    let mut skipped: Vec<(PathBuf, Skip)> = Vec::new();
+    let mut videos = Vec::new();
+    let mut extras = Vec::new();
     
-    println!("{}",
-        if show_name.is_some() { "I think this show is:" }
-        else { "I don't know what this show is:" }
-    );
-    let show_name = input.text(show_name.unwrap_or(String::new()));
-    println!("");
+    for (dir, files) in groups {
+        let mut kept = Vec::new();
+        let mut sidecars = Vec::new();
+        
+        for file in files {
+            match filter.check(&file) {
+                Ok(()) => kept.push(file),
+                Err(Skip::NotVideo(_)) if is_sidecar(&file) => sidecars.push(file),
+                Err(skip) => skipped.push((file, skip)),
+            }
+        }
+        
+        if kept.is_empty() {
+            skipped.extend(sidecars.into_iter().map(|file| (file, Skip::Alone)));
+        }
+        else {
+            videos.push((dir, kept));
+            extras.push(sidecars);
+        }
+    }
     
-    // Guess the season number.
-    let season_number = guesser.get_season_number().unwrap_or(1);
+    let groups = videos;
     
-    println!("I think this season is:");
-    let season_number = input.number(season_number);
-    println!("");
+    if groups.is_empty() {
+        quit("There aren't any videos here.");
+    }
     
-    // Create episode objects.
-    let mut factory = EpisodeFactory::new(&show_name, season_number, &cleaner);
+    // The show and season of each folder.
+    let names: Vec<(String, u32)> = groups.iter()
+        .map(|(dir, files)| guess_folder(dir, files, &args, &cleaner, &mut input, interactive))
+        .collect();
     
-    for file in files {
-        factory.insert(file.path())
-            .unwrap_or_else(|e| quit(e.as_ref()));
+    for ((dir, _), (show_name, season_number)) in groups.iter().zip(&names) {
+        if args.recursive {
+            say!("Show: {}, Season {} ({})", show_name, season_number, dir.display());
+        }
+        else {
+            say!("Show: {}, Season {}", show_name, season_number);
+        }
     }
     
-    // Preview.
-    println!("How's this?");
-    println!("");
+    say!("");
+    
+    // Move into a library instead of renaming in place.
+    let library = args.library.as_ref().map(|library| {
+        let library = env::current_dir()
+            .map(|dir| dir.join(library))
+            .unwrap_or_else(|_| quit("Can't find the library path!"));
+        
+        say!("Moving into library {}", library.display());
+        say!("");
+        
+        library
+    });
     
-    let episodes = factory.get_all();
+    // Create episode objects, for each folder.
+    let mut factories = Vec::new();
+    let mut sidecars = Vec::new();
     
-    for ep in &episodes {
-        println!("{}", ep);
+    for (((dir, files), (show_name, season_number)), extra) in groups.iter().zip(&names).zip(&extras) {
+        let mut factory = EpisodeFactory::new(show_name, *season_number, &cleaner);
+        factory.set_template(template.clone());
+        factory.set_episodes_per_season(args.episodes_per_season.clone());
+        
+        if let Some(library) = &library {
+            factory.set_library(library);
+        }
+        
+        let mut created = Vec::new();
+        
+        for file in files {
+            match factory.create(file) {
+                Ok(episode) => created.push(episode),
+                Err(e) => skipped.push((file.clone(), Skip::Unknown(e))),
+            }
+        }
+        
+        // Specials without a number go after the numbered ones.
+        created.sort_by_key(Episode::needs_number);
+        
+        for episode in created {
+            factory.add(episode)
+                .unwrap_or_else(|e| {
+                    if args.recursive { quit(&format!("{}: {}", dir.display(), e)) }
+                    else { quit(e.as_ref()) }
+                });
+        }
+        
+        // Find the video for each subtitle, etc.
+        let episodes = factory.get_all();
+        
+        for file in extra {
+            match Sidecar::find(file, &episodes) {
+                Some(sidecar) => sidecars.push(sidecar),
+                None => skipped.push((file.clone(), Skip::Alone)),
+            }
+        }
+        
+        drop(episodes);
+        factories.push(factory);
     }
     
-    println!("");
-    println!("Do you want to rename these?");
+    // Changes are made to copies, the factories are done.
+    let mut episodes: Vec<Episode> = factories.iter()
+        .flat_map(|factory| factory.get_all())
+        .cloned()
+        .collect();
+    
+    drop(factories);
     
-    if input.confirm() {
-        println!("");
-        println!("Working...");
+    let mut review = Review::new();
+    
+    loop {
+        // Preview.
+        say!("How's this? ({})", transfer.mode());
+        say!("");
+        
+        // Numbered, so they can be picked out to change.
+        let indent = if interactive { "    " } else { "  " };
         
-        // Rename all the files.
         for (i, ep) in episodes.iter().enumerate() {
-            println!("File: {}", i);
-            ep.rename().unwrap_or_else(|_| quit("Failed to rename file."));
+            if interactive {
+                say!("{:>2}. {}", i + 1, ep);
+            }
+            else {
+                say!("{}", ep);
+            }
+            
+            for sidecar in sidecars.iter().filter(|sidecar| sidecar.episode == ep.path) {
+                say!("{}{:?} -> {:?}",
+                    indent,
+                    sidecar.path.file_name().unwrap_or_default(),
+                    sidecar.destination(ep).file_name().unwrap_or_default(),
+                );
+            }
+        }
+        
+        say!("");
+        
+        let specials = episodes.iter().filter(|ep| ep.season == 0).count();
+        
+        if specials > 0 {
+            say!("{} specials are in season 0.", specials);
+            say!("");
+        }
+        
+        if !skipped.is_empty() {
+            say!("Skipped {} files: {}", skipped.len(), summary(&skipped));
+            
+            for (file, skip) in &skipped {
+                say!("{:?} - {}", file.file_name().unwrap_or_default(), skip);
+            }
+            
+            say!("");
+        }
+        
+        if episodes.is_empty() {
+            quit("There aren't any episodes here.");
+        }
+        
+        // Sidecars are added once the videos are settled.
+        let steps: Vec<Step> = episodes.iter()
+            .map(|ep| Step::new(&ep.path, ep.destination()))
+            .collect();
+        
+        let (batch, notes) = prepare(steps, &sidecars, &transfer, args.policy);
+        
+        // Show the plan and stop.
+        if args.dry_run {
+            match args.format {
+                Format::Json => {
+                    let warnings = skipped.iter()
+                        .map(|(file, skip)| format!("Skipped {}: {}", file.display(), skip))
+                        .chain(notes)
+                        .collect();
+                    
+                    let episodes: Vec<&Episode> = episodes.iter().collect();
+                    
+                    println!("{}", Plan::new(transfer.mode(), &episodes, &sidecars, batch.steps(), warnings).to_json());
+                },
+                Format::Text => {
+                    say!("Dry run, nothing was changed.");
+                },
+            }
+            
+            std::process::exit(0);
+        }
+        
+        say!("Do you want to {} these?", transfer.mode());
+        
+        if !interactive {
+            say!("");
+            run(&batch);
+            break;
+        }
+        
+        say!("(Or type a number to change that file, or 'clean' to tidy the titles again.)");
+        
+        match ask(&mut input, episodes.len()) {
+            Answer::Yes => {
+                say!("");
+                run(&batch);
+                break;
+            },
+            Answer::No => {
+                say!("");
+                say!("Okay, I did nothing.");
+                break;
+            },
+            Answer::Edit(index) => {
+                say!("");
+                
+                // Along with its subtitles, etc.
+                if let Some(ep) = review.edit(&mut input, &mut episodes, index) {
+                    let (excluded, kept) = sidecars.into_iter()
+                        .partition(|sidecar: &Sidecar| sidecar.episode == ep.path);
+                    
+                    sidecars = kept;
+                    skipped.push((ep.path, Skip::Excluded));
+                    skipped.extend(excluded.into_iter().map(|sidecar| (sidecar.path, Skip::Excluded)));
+                }
+            },
+            Answer::Clean => {
+                // The rules may have changed since, keep the old ones if they're broken.
+                match Cleaner::load(&rules_path, &acronyms_path) {
+                    Ok(reloaded) => {
+                        cleaner = reloaded;
+                        say!("Exclude DB loaded {} rules.", cleaner.size());
+                    },
+                    Err(err) => say!("{}", err),
+                }
+                
+                review.clean(&mut episodes, &cleaner);
+            },
+        }
+        
+        say!("");
+    }
+    
+    // Wait for exit (good for working with midnight commander).
+    if interactive {
+        input.pause();
+    }
+}
+
+/// Resolve conflicts and check everything can happen, before asking.
+/// This gives the batch and notes about any conflicts.
+fn prepare<'t>(steps: Vec<Step>, sidecars: &[Sidecar], transfer: &'t Transfer, policy: Policy) -> (Batch<'t>, Vec<String>) {
+    // Don't overwrite anything that's already there.
+    let (steps, notes) = resolve_with_sidecars(steps, sidecars, transfer.mode(), policy)
+        .unwrap_or_else(|errors| {
+            for err in errors {
+                say!("{}", err);
+            }
+            quit("Nothing was changed, try --on-conflict skip, suffix or larger.");
+        });
+    
+    for note in &notes {
+        say!("{}", note);
+    }
+    
+    if !notes.is_empty() {
+        say!("");
+    }
+    
+    let mut batch = Batch::new(transfer);
+    
+    for step in steps {
+        batch.push(step);
+    }
+    
+    if let Err(errors) = batch.validate() {
+        for err in errors {
+            say!("{}", err);
+        }
+        quit("Nothing was changed.");
+    }
+    
+    (batch, notes)
+}
+
+/// Run a batch, keeping a journal so it can be undone.
+fn run(batch: &Batch) {
+    say!("Working...");
+    
+    // Keep track of everything so it can be undone.
+    let mut journal = get_journal_dir()
+        .and_then(|dir| Journal::create(dir).map_err(|_| "Failed to create the undo journal."))
+        .unwrap_or_else(|e| quit(e));
+    
+    // Rename all the files, or none of them.
+    if let Err(err) = batch.run(&mut journal) {
+        say!("{}", err);
+        
+        // Nothing left to undo.
+        if err.rollback.is_empty() {
+            journal.discard().unwrap_or(());
         }
         
-        println!("All done!");
+        quit("Failed to rename files.");
+    }
+    
+    say!("All done! {} files.", batch.len());
+    say!("Changed your mind? Run: tv-rename undo");
+    say!("Journal: {}", journal.path().display());
+}
+
+/// Run a plan from a dry run.
+/// Things may have changed since, so it's all checked again.
+fn apply(path: &Path, args: &Args, interactive: bool) -> ! {
+    let mut input = Input::new(input_errors);
+    
+    let plan = Plan::load(path)
+        .unwrap_or_else(|e| quit(&e));
+    
+    let transfer = Transfer::new(plan.mode, args.verify, print_progress);
+    
+    say!("Applying {}", path.display());
+    say!("");
+    say!("How's this? ({})", transfer.mode());
+    say!("");
+    
+    for item in &plan.items {
+        say!("{:?} -> {:?}", item.source, item.destination);
+        
+        for warning in &item.warnings {
+            say!("  {}", warning);
+        }
+    }
+    
+    say!("");
+    
+    let (batch, _) = prepare(plan.steps(), &[], &transfer, args.policy);
+    
+    if args.dry_run {
+        say!("Dry run, nothing was changed.");
+        std::process::exit(0);
+    }
+    
+    say!("Do you want to {} these?", transfer.mode());
+    
+    if !interactive || input.confirm() {
+        say!("");
+        run(&batch);
     }
     else {
-        println!("");
-        println!("Okay, I did nothing.");
+        say!("");
+        say!("Okay, I did nothing.");
     }
     
-    // Wait for exit (good for working with midnight commander).
-    input.pause();
+    if interactive {
+        input.pause();
+    }
+    
+    std::process::exit(0);
+}
+
+/// Undo the last run, or the journal given after 'undo'.
+fn undo(name: Option<&String>, yes: bool) -> ! {
+    let mut input = Input::new(input_errors);
+    
+    let dir = get_journal_dir()
+        .unwrap_or_else(|e| quit(e));
+    
+    let journals = journal::list(&dir)
+        .unwrap_or_else(|_| quit("Can't read the journals!"));
+    
+    // Find a journal by name, or the latest.
+    let path = match name {
+        Some(name) => {
+            journals.iter()
+                .find(|path| path.file_stem().is_some_and(|stem| stem == name.as_str()))
+                .cloned()
+                .or_else(|| Some(PathBuf::from(name)).filter(|path| path.is_file()))
+                .unwrap_or_else(|| {
+                    for path in &journals {
+                        say!("{}", path.file_stem().unwrap().to_string_lossy());
+                    }
+                    quit("Can't find that journal, try one of the above.");
+                })
+        },
+        None => {
+            journals.last()
+                .cloned()
+                .unwrap_or_else(|| quit("Nothing to undo."))
+        },
+    };
+    
+    let entries = journal::read(&path)
+        .unwrap_or_else(|e| quit(&e));
+    
+    say!("Undo {}?", path.display());
+    say!("");
+    
+    for entry in entries.iter().rev() {
+        say!("{:?} -> {:?} ({})", entry.to, entry.from, entry.mode);
+    }
+    
+    say!("");
+    
+    if !yes && !input.confirm() {
+        say!("");
+        say!("Okay, I did nothing.");
+        std::process::exit(0);
+    }
+    
+    say!("");
+    
+    let mut remaining = Vec::new();
+    
+    // Backwards, in case one file moved through another's old name.
+    for entry in entries.iter().rev() {
+        if let Err(err) = entry.undo() {
+            say!("Skipped: {}", err);
+            remaining.insert(0, entry.clone());
+        }
+    }
+    
+    journal::finish(&path, &remaining)
+        .unwrap_or_else(|_| quit("Failed to update the journal."));
+    
+    if remaining.is_empty() {
+        say!("All done!");
+        std::process::exit(0);
+    }
+    
+    quit(&format!("Couldn't undo {} files.", remaining.len()));
 }
 
 /// Quit message. Kinda like panic, but prettier.
 fn quit(message: &str) -> ! {
-    println!("{}\nQuitting.", message);
+    say!("{}\nQuitting.", message);
     std::process::exit(1)
 }
 
@@ -132,25 +598,138 @@ fn input_errors(err: ReadlineError) -> () {
             quit("\nCtrl-D");
         }
         err => {
-            println!("\nError: {:?}", err);
+            say!("\nError: {:?}", err);
             quit("uhh");
         }
     }
 }
 
-/// Get the directory from arg 1.
+/// Print the progress of a copy.
+fn print_progress(copied: u64, total: u64) {
+    let percent = (copied * 100).checked_div(total).unwrap_or(100);
+    print!("\rCopying... {}%", percent);
+    
+    if copied >= total {
+        say!("");
+    }
+    
+    io::stdout().flush().unwrap_or(());
+}
+
+/// Get the directory from the arguments.
 /// Or, if not provided, the current working directory.
-fn get_directory() -> Result<PathBuf, io::Error> {
-    match env::args().skip(1).next() {
-        Some(path) => PathBuf::from(&path).canonicalize(),
+fn get_directory(path: Option<&PathBuf>) -> Result<PathBuf, io::Error> {
+    match path {
+        Some(path) => path.canonicalize(),
         None => env::current_dir(),
     }
 }
 
-/// Read the directory as a vector of entries.
-fn read_directory(path: &PathBuf) -> Result<Vec<DirEntry>, io::Error> {
+/// Read the directory as a vector of paths.
+fn read_directory(path: &PathBuf) -> Result<Vec<PathBuf>, io::Error> {
     fs::read_dir(path).map(|dir| {
-        dir.map(|entry| entry.unwrap())
+        dir.map(|entry| entry.unwrap().path())
         .collect()
     })
 }
+
+/// Read the files of every folder inside a directory, including itself.
+/// Each folder with files is a group, in order. Links to folders aren't
+/// followed, so nothing is read twice.
+fn walk_directory(path: &Path) -> Result<Vec<(PathBuf, Vec<PathBuf>)>, io::Error> {
+    let mut groups = Vec::new();
+    let mut folders = vec![path.to_path_buf()];
+    
+    while let Some(folder) = folders.pop() {
+        let mut files = Vec::new();
+        let mut children = Vec::new();
+        
+        for entry in fs::read_dir(&folder)? {
+            let entry = entry?;
+            
+            if entry.file_type()?.is_dir() {
+                children.push(entry.path());
+            }
+            else {
+                files.push(entry.path());
+            }
+        }
+        
+        if !files.is_empty() {
+            files.sort();
+            groups.push((folder, files));
+        }
+        
+        // Backwards, so they come off the stack in order.
+        children.sort();
+        folders.extend(children.into_iter().rev());
+    }
+    
+    Ok(groups)
+}
+
+/// Guess the show and season of a folder, unless they're given.
+/// One folder is always checked, but when doing many this only asks if the
+/// guess isn't clear. Without anyone to ask, only a clear guess is used.
+fn guess_folder(dir: &Path, files: &[PathBuf], args: &Args, cleaner: &Cleaner, input: &mut Input, interactive: bool) -> (String, u32) {
+    // The guesser object finds the most likely show/season.
+    let guesser = Guesser::new(files);
+    
+    let show_name = match (&args.show_name, guesser.get_certain_show_name()) {
+        (Some(show_name), _) => show_name.clone(),
+        (None, Some(show_name)) if args.recursive || !interactive => {
+            cleaner.clean(&show_name, Scope::ShowName)
+        },
+        (None, None) if !interactive => {
+            if args.recursive {
+                quit(&format!("I'm not sure what show {} is, try --show.", dir.display()));
+            }
+            quit("I'm not sure what this show is, try --show.");
+        },
+        (None, _) => {
+            let show_name = guesser.get_show_name()
+                .map(|name| cleaner.clean(&name, Scope::ShowName));
+            
+            if args.recursive {
+                say!("I'm not sure what show this is: {}", dir.display());
+            }
+            else if show_name.is_some() {
+                say!("I think this show is:");
+            }
+            else {
+                say!("I don't know what this show is:");
+            }
+            
+            let show_name = input.text(show_name.unwrap_or_default());
+            say!("");
+            show_name
+        },
+    };
+    
+    let season_number = match (args.season, guesser.get_certain_season_number()) {
+        (Some(season_number), _) => season_number,
+        (None, Some(season_number)) if args.recursive || !interactive => season_number,
+        (None, None) if !interactive => {
+            if args.recursive {
+                quit(&format!("I'm not sure what season {} is, try --season.", dir.display()));
+            }
+            quit("I'm not sure what season this is, try --season.");
+        },
+        (None, _) => {
+            let season_number = guesser.get_season_number().unwrap_or(1);
+            
+            if args.recursive {
+                say!("I'm not sure what season this is: {}", dir.display());
+            }
+            else {
+                say!("I think this season is:");
+            }
+            
+            let season_number = input.number(season_number);
+            say!("");
+            season_number
+        },
+    };
+    
+    (show_name, season_number)
+}
@@ -2,6 +2,9 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 
+/// Any date, in any order.
+const DATE: &str = r"(?:(?:19|20)\d{2}[.\-_ ]\d{1,2}[.\-_ ]\d{1,2}|\d{1,2}[.\-_ ]\d{1,2}[.\-_ ](?:19|20)\d{2})";
+
 lazy_static! {
     // Capture everything before the season/episode identifier.
     static ref SHOW_NAME: Regex =
@@ -25,7 +28,7 @@ lazy_static! {
     
     // Capture everything after the season/episode identifier.
     static ref EPISODE_NAME: Regex =
-        Regex::new(r"(?i:s\d+e\d+|\d+of\d+|\d+x\d+|(?i:ep|episode|part)\W*\d+)(.+)\.(?i:[a-z0-9]+)$")
+        Regex::new(r"(?i:s\d+e\d+(?:-?e\d+)*(?:-\d{1,3}\b)?|\d+of\d+|\d+x\d+(?:-(?:\d+x)?\d{1,3}\b)?|(?i:ep|episode|part)\W*\d+)(.+)\.(?i:[a-z0-9]+)$")
         .unwrap();
     
     // Capture the episode number by --of--
@@ -33,25 +36,81 @@ lazy_static! {
         Regex::new(r"(?i:(\d+)\W*of\W*\d+)").unwrap();
     
     // Capture the episode number by S--E--
+    // Multi-episodes can follow as S--E--E--, S--E---E-- or S--E----.
     static ref EPISODE_NUMBER_BY_SE: Regex =
-        Regex::new(r"(?i:s\d{2,}e(\d{2,}))").unwrap();
+        Regex::new(r"(?i:s\d{2,}e(\d{2,})(?:(?:-?e(\d{2,}))+|-(\d{1,3})\b)?)").unwrap();
     
     // Capture the episode number by --x--
+    // Multi-episodes can follow as --x---- or --x----x--.
     static ref EPISODE_NUMBER_BY_X: Regex =
-        Regex::new(r"(?i:\d+x(\d+))").unwrap();
+        Regex::new(r"(?i:\d+x(\d+)(?:-(?:\d+x)?(\d{1,3})\b)?)").unwrap();
     
     // Capture the episode by association to a word.
     static ref EPISODE_NUMBER_BY_NAME: Regex =
         Regex::new(r"(?i:episode|ep|part)\W*(\d+)").unwrap();
     
+    // Capture a bracketed year after the show name, like 'Show (2019)'.
+    static ref SHOW_YEAR: Regex =
+        Regex::new(r"^(.+?)\s*\(((?:19|20)\d{2})\)$").unwrap();
+    
+    // Capture a date with the year first, like 2019.03.14 or 2019-03-14.
+    static ref DATE_YMD: Regex =
+        Regex::new(r"(?:^|\D)((?:19|20)\d{2})[.\-_ ](\d{1,2})[.\-_ ](\d{1,2})(?:\D|$)").unwrap();
+    
+    // Capture a date with the year last, like 14.03.2019 or 03-14-2019.
+    static ref DATE_DMY: Regex =
+        Regex::new(r"(?:^|\D)(\d{1,2})[.\-_ ](\d{1,2})[.\-_ ]((?:19|20)\d{2})(?:\D|$)").unwrap();
+    
+    // Capture everything before a date.
+    static ref SHOW_NAME_BY_DATE: Regex =
+        Regex::new(&format!(r"^(.*?\D){}(?:\D|$)", DATE)).unwrap();
+    
+    // Capture everything after a date.
+    static ref EPISODE_NAME_BY_DATE: Regex =
+        Regex::new(&format!(r"(?:^|\D){}((?:\D.*)?)\.(?i:[a-z0-9]+)$", DATE)).unwrap();
+    
+    // Capture the show, absolute number and the rest of an anime release,
+    // like '[Group] Show - 137v2 [1080p][ABCD1234].mkv'.
+    static ref ABSOLUTE: Regex =
+        Regex::new(r"^\[[^\]]*\]\s*(.+?)\s+-\s+(\d{1,4})(?i:v\d+)?\b(.*)\.(?i:[a-z0-9]+)$").unwrap();
+    
+    // Tags in brackets, like '[1080p]' or '(BD x264)'.
+    static ref BRACKETS: Regex =
+        Regex::new(r"\[[^\]]*\]|\([^)]*\)").unwrap();
+    
+    // Capture a special, like 'SP01', 'OVA 2', 'Special' or 'Christmas Special'.
+    static ref SPECIAL: Regex =
+        Regex::new(r"(?i)\b(?:sp\W?(\d{1,3})|(?:specials?|ova|oad)(?:\W?(\d{1,3}))?)\b").unwrap();
+    
+    // Capture everything before a special.
+    static ref SHOW_NAME_BY_SPECIAL: Regex =
+        Regex::new(r"(?i)^(.+?)\b(?:sp\W?\d{1,3}|specials?|ova|oad)\b").unwrap();
+    
+    // Capture everything after a numbered special.
+    static ref EPISODE_NAME_BY_SPECIAL: Regex =
+        Regex::new(r"(?i)\b(?:sp|specials?|ova|oad)\W?\d{1,3}\b(.+)\.(?i:[a-z0-9]+)$").unwrap();
+    
     // Capture the file extension.
     static ref EXTENSION: Regex =
         Regex::new(r"\.(\w+)$").unwrap();
 }
 
 /// Get the show name.
+/// Anime is named after the group, and daily shows before the date.
 pub fn parse_show_name(path: &str) -> Option<String> {
-    SHOW_NAME.captures(path).map(|m| String::from(&m[1]))
+    ABSOLUTE.captures(path)
+        .or_else(|| SHOW_NAME.captures(path))
+        .or_else(|| SHOW_NAME_BY_DATE.captures(path))
+        .or_else(|| SHOW_NAME_BY_SPECIAL.captures(path))
+        .map(|m| String::from(&m[1]))
+}
+
+/// Split a bracketed year from a show name.
+pub fn parse_show_year(show_name: &str) -> (String, Option<u32>) {
+    match SHOW_YEAR.captures(show_name.trim()) {
+        Some(m) => (String::from(&m[1]), m[2].parse::<u32>().ok()),
+        None => (String::from(show_name.trim()), None),
+    }
 }
 
 /// Get the season number.
@@ -73,6 +132,12 @@ pub fn parse_season_number(path: &str)-> Option<u32> {
             .map(|m| String::from(&m[1]));
     }
     
+    // Anime is numbered across seasons, and tags like '[1920x1080]'
+    // aren't a season either.
+    if ABSOLUTE.is_match(path) {
+        return caps.and_then(|num| num.parse::<u32>().ok());
+    }
+    
     // by --x--
     if caps.is_none() {
         caps = SEASON_NUMBER_BY_X
@@ -80,6 +145,17 @@ pub fn parse_season_number(path: &str)-> Option<u32> {
             .map(|m| String::from(&m[1]));
     }
     
+    // by date, daily shows are a season per year
+    if caps.is_none() {
+        caps = parse_air_date(path)
+            .map(|(year, _, _)| year.to_string());
+    }
+    
+    // by special, these are season 0
+    if caps.is_none() && SPECIAL.is_match(path) {
+        caps = Some(String::from("0"));
+    }
+    
     // Parse number, errors are None.
     if let Some(num) = caps {
         return num.parse::<u32>().ok();
@@ -89,54 +165,144 @@ pub fn parse_season_number(path: &str)-> Option<u32> {
 }
 
 /// Get the episode name.
+/// Daily shows are named after the date. Anime is named after the number,
+/// without any tags.
 pub fn parse_episode_name(path: &str) -> Option<String> {
+    if let Some(m) = ABSOLUTE.captures(path) {
+        return Some(BRACKETS.replace_all(&m[3], "").to_string());
+    }
+    
     EPISODE_NAME
         .captures(path)
+        .or_else(|| EPISODE_NAME_BY_DATE.captures(path))
+        .or_else(|| EPISODE_NAME_BY_SPECIAL.captures(path))
         .map(|m| String::from(&m[1]))
         .or(Some(String::new()))
 }
 
-/// Get the episode number.
-pub fn parse_episode_number(path: &str) -> Option<u32> {
+/// Get the episode number, and the name of the rule that found it.
+/// This is a range of (first, last) episodes, which are equal for
+/// a single episode file.
+/// The rules are tried in order: 'absolute', 'of', 'S--E--', '-x-' and 'name'.
+pub fn parse_episode_number_rule(path: &str) -> Option<(u32, u32, &'static str)> {
     
-    let mut caps: Option<String> = None;
+    let mut caps: Option<(String, Option<String>, &'static str)> = None;
+    
+    // by [group] show - ---
+    if caps.is_none() {
+        caps = ABSOLUTE
+            .captures(path)
+            .map(|m| (String::from(&m[2]), None, "absolute"));
+    }
     
     // by --of--
     if caps.is_none() {
         caps = EPISODE_NUMBER_BY_OF
             .captures(path)
-            .map(|m| String::from(&m[1]));
+            .map(|m| (String::from(&m[1]), None, "of"));
     }
     
     // by S--E--
     if caps.is_none() {
         caps = EPISODE_NUMBER_BY_SE
             .captures(path)
-            .map(|m| String::from(&m[1]));
+            .map(|m| (
+                String::from(&m[1]),
+                m.get(2).or(m.get(3)).map(|last| String::from(last.as_str())),
+                "S--E--",
+            ));
     }
     
     // by --x--
     if caps.is_none() {
         caps = EPISODE_NUMBER_BY_X
             .captures(path)
-            .map(|m| String::from(&m[1]));
+            .map(|m| (
+                String::from(&m[1]),
+                m.get(2).map(|last| String::from(last.as_str())),
+                "-x-",
+            ));
     }
     
     // by name
     if caps.is_none() {
         caps = EPISODE_NUMBER_BY_NAME
             .captures(path)
-            .map(|m| String::from(&m[1]));
+            .map(|m| (String::from(&m[1]), None, "name"));
     }
     
-    // Parse number, errors are None.
-    if let Some(num) = caps {
-        return num.parse::<u32>().ok();
+    // Parse numbers, errors are None.
+    if let Some((first, last, rule)) = caps {
+        let first = first.parse::<u32>().ok()?;
+        
+        // A backwards range is nonsense, so just keep the first.
+        let last = last
+            .and_then(|last| last.parse::<u32>().ok())
+            .filter(|last| *last > first)
+            .unwrap_or(first);
+        
+        return Some((first, last, rule));
     }
     
     None
 }
 
+/// Get the absolute number of an anime episode.
+/// These count up from the first episode, ignoring seasons.
+pub fn parse_absolute_number(path: &str) -> Option<u32> {
+    ABSOLUTE
+        .captures(path)
+        .and_then(|m| m[2].parse::<u32>().ok())
+}
+
+/// Whether this is a special, and its number if it has one.
+pub fn parse_special(path: &str) -> Option<Option<u32>> {
+    SPECIAL
+        .captures(path)
+        .map(|m| m.get(1).or(m.get(2)).and_then(|num| num.as_str().parse::<u32>().ok()))
+}
+
+/// Get the air date of a daily show, as (year, month, day).
+/// The year is either first, or last after the day and month. A year last
+/// date is read day first, unless that isn't a real date.
+pub fn parse_air_date(path: &str) -> Option<(u32, u32, u32)> {
+    if let Some(m) = DATE_YMD.captures(path) {
+        let year = m[1].parse::<u32>().ok()?;
+        let month = m[2].parse::<u32>().ok()?;
+        let day = m[3].parse::<u32>().ok()?;
+        
+        return check_date(year, month, day);
+    }
+    
+    let m = DATE_DMY.captures(path)?;
+    let first = m[1].parse::<u32>().ok()?;
+    let second = m[2].parse::<u32>().ok()?;
+    let year = m[3].parse::<u32>().ok()?;
+    
+    check_date(year, second, first)
+        .or_else(|| check_date(year, first, second))
+}
+
+/// Whether this day exists.
+fn check_date(year: u32, month: u32, day: u32) -> Option<(u32, u32, u32)> {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    
+    let days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    
+    if day >= 1 && day <= days {
+        Some((year, month, day))
+    }
+    else {
+        None
+    }
+}
+
 /// Get the extension.
 pub fn parse_extension(path: &str) -> Option<String> {
     EXTENSION
@@ -149,6 +315,10 @@ pub fn parse_extension(path: &str) -> Option<String> {
 mod test {
     use super::*;
     
+    fn parse_episode_number(path: &str) -> Option<(u32, u32)> {
+        parse_episode_number_rule(path).map(|(first, last, _)| (first, last))
+    }
+    
     #[test]
     fn test_show_name_1() {
         let actual = parse_show_name("Friends.5x02.whatever.mp4");
@@ -163,6 +333,16 @@ mod test {
         assert_eq!(expected, actual.unwrap());
     }
     
+    #[test]
+    fn test_show_year() {
+        let actual = parse_show_year("Doctor Who (2005)");
+        assert_eq!((String::from("Doctor Who"), Some(2005)), actual);
+        
+        // Only bracketed years, this is part of the name.
+        let actual = parse_show_year("Blade Runner 2049");
+        assert_eq!((String::from("Blade Runner 2049"), None), actual);
+    }
+    
     // This returns 'series 5' which is kind of correct. But I can't think
     // of a better regex right now.
     // #[test]
@@ -248,31 +428,164 @@ mod test {
     #[test]
     fn test_episode_number_by_of() {
         let actual = parse_episode_number("Grand.Designs.Series.5.2of9.mp4");
-        let expected = 2;
+        let expected = (2, 2);
         assert_eq!(expected, actual.unwrap());
     }
     
     #[test]
     fn test_episode_number_by_se() {
         let actual = parse_episode_number("Grand.Designs.s05e02.mp4");
-        let expected = 2;
+        let expected = (2, 2);
         assert_eq!(expected, actual.unwrap());
     }
     
     #[test]
     fn test_episode_number_by_x() {
         let actual = parse_episode_number("Grand.Designs.5x2.mp4");
-        let expected = 2;
+        let expected = (2, 2);
         assert_eq!(expected, actual.unwrap());
     }
     
     #[test]
     fn test_episode_number_by_name() {
         let actual = parse_episode_number("Grand.Designs.ep2.mp4");
-        let expected = 2;
+        let expected = (2, 2);
         assert_eq!(expected, actual.unwrap());
     }
     
+    #[test]
+    fn test_episode_number_rule() {
+        assert_eq!(Some((2, 2, "of")), parse_episode_number_rule("Friends.2of9.mp4"));
+        assert_eq!(Some((4, 4, "S--E--")), parse_episode_number_rule("Friends.S01E04.mp4"));
+        assert_eq!(Some((4, 4, "-x-")), parse_episode_number_rule("Friends.1x04.mp4"));
+        assert_eq!(Some((3, 3, "name")), parse_episode_number_rule("Friends.Part.3.mp4"));
+        assert_eq!(None, parse_episode_number_rule("Friends.mp4"));
+    }
+    
+    #[test]
+    fn test_episode_number_multi_se() {
+        let actual = parse_episode_number("Show.S02E05E06.mkv");
+        assert_eq!((5, 6), actual.unwrap());
+        
+        let actual = parse_episode_number("Show.S01E01-E03.mkv");
+        assert_eq!((1, 3), actual.unwrap());
+        
+        let actual = parse_episode_number("Show.S01E01-02.mkv");
+        assert_eq!((1, 2), actual.unwrap());
+    }
+    
+    #[test]
+    fn test_episode_number_multi_x() {
+        let actual = parse_episode_number("Show.1x01-02.avi");
+        assert_eq!((1, 2), actual.unwrap());
+        
+        let actual = parse_episode_number("Show.1x01-1x02.avi");
+        assert_eq!((1, 2), actual.unwrap());
+    }
+    
+    #[test]
+    fn test_episode_number_multi_bad() {
+        // Not a range, just a resolution tag.
+        let actual = parse_episode_number("Show.S01E01-720p.mkv");
+        assert_eq!((1, 1), actual.unwrap());
+        
+        // Backwards.
+        let actual = parse_episode_number("Show.1x05-02.avi");
+        assert_eq!((5, 5), actual.unwrap());
+    }
+    
+    #[test]
+    fn test_episode_name_multi() {
+        let actual = parse_episode_name("Show.S02E05E06.one.with.ross.mp4");
+        assert_eq!(".one.with.ross", actual.unwrap());
+        
+        let actual = parse_episode_name("Show.1x01-02.one.with.ross.mp4");
+        assert_eq!(".one.with.ross", actual.unwrap());
+    }
+    
+    #[test]
+    fn test_air_date() {
+        assert_eq!(Some((2019, 3, 14)), parse_air_date("The.Daily.Show.2019.03.14.Guest.Name.mkv"));
+        assert_eq!(Some((2019, 3, 14)), parse_air_date("The Daily Show - 2019-03-14 - Guest Name.mkv"));
+        assert_eq!(Some((2019, 3, 14)), parse_air_date("the_daily_show_2019_3_14.mkv"));
+        assert_eq!(Some((2019, 3, 4)), parse_air_date("The.Daily.Show.04.03.2019.mkv"));
+        assert_eq!(Some((2019, 3, 14)), parse_air_date("The.Daily.Show.14.03.2019.mkv"));
+        assert_eq!(Some((2019, 3, 14)), parse_air_date("The.Daily.Show.03-14-2019.mkv"));
+        assert_eq!(Some((2020, 2, 29)), parse_air_date("Show.2020.02.29.mkv"));
+    }
+    
+    #[test]
+    fn test_air_date_bad() {
+        assert_eq!(None, parse_air_date("Show.2019.02.29.mkv"));
+        assert_eq!(None, parse_air_date("Show.2019.13.01.mkv"));
+        assert_eq!(None, parse_air_date("Show.S01E02.1080p.mkv"));
+        assert_eq!(None, parse_air_date("Show.12019.03.14.mkv"));
+    }
+    
+    #[test]
+    fn test_air_date_names() {
+        let path = "The.Daily.Show.2019.03.14.Guest.Name.mkv";
+        
+        assert_eq!("The.Daily.Show.", parse_show_name(path).unwrap());
+        assert_eq!(".Guest.Name", parse_episode_name(path).unwrap());
+        assert_eq!(Some(2019), parse_season_number(path));
+        assert_eq!(None, parse_episode_number(path));
+        
+        assert_eq!("", parse_episode_name("Show.14.03.2019.mkv").unwrap());
+    }
+    
+    #[test]
+    fn test_absolute() {
+        let path = "[SubGroup] Show Name - 137 [1080p][ABCD1234].mkv";
+        
+        assert_eq!(Some(137), parse_absolute_number(path));
+        assert_eq!(Some((137, 137, "absolute")), parse_episode_number_rule(path));
+        assert_eq!("Show Name", parse_show_name(path).unwrap());
+        assert_eq!(" ", parse_episode_name(path).unwrap());
+        assert_eq!(None, parse_season_number(path));
+        
+        assert_eq!(Some(5), parse_absolute_number("[Group] Show - 05v2 (BD 1920x1080 x264).mkv"));
+        assert_eq!(Some(5), parse_absolute_number("[Group] Show - 05.mkv"));
+        assert_eq!(Some(2), parse_season_number("[Group] Show Season 2 - 05 [1080p].mkv"));
+        assert_eq!(" - The Title ", parse_episode_name("[Group] Show - 05 - The Title [1080p].mkv").unwrap());
+    }
+    
+    #[test]
+    fn test_absolute_bad() {
+        // The group is needed.
+        assert_eq!(None, parse_absolute_number("Show - 137 [1080p].mkv"));
+        
+        // A resolution, not an episode.
+        assert_eq!(None, parse_absolute_number("[Group] Show - 1080p.mkv"));
+        
+        assert_eq!(None, parse_absolute_number("[Group] Show S01E05 [1080p].mkv"));
+    }
+    
+    #[test]
+    fn test_special() {
+        assert_eq!(Some(Some(1)), parse_special("Show.SP01.mkv"));
+        assert_eq!(Some(Some(2)), parse_special("Show.OVA.2.mkv"));
+        assert_eq!(Some(None), parse_special("Show.OVA.mkv"));
+        assert_eq!(Some(None), parse_special("Show.Christmas.Special.mkv"));
+        assert_eq!(Some(None), parse_special("Show.Special.2019.mkv"));
+        assert_eq!(None, parse_special("Show.S01E02.Spectre.mkv"));
+        assert_eq!(None, parse_special("Show.S01E02.mkv"));
+    }
+    
+    #[test]
+    fn test_special_names() {
+        assert_eq!("Show.", parse_show_name("Show.SP01.The.Movie.mkv").unwrap());
+        assert_eq!("Show.Christmas.", parse_show_name("Show.Christmas.Special.mkv").unwrap());
+        assert_eq!(".The.Movie", parse_episode_name("Show.SP01.The.Movie.mkv").unwrap());
+        
+        assert_eq!(Some(0), parse_season_number("Show.SP01.mkv"));
+        assert_eq!(Some(0), parse_season_number("Show.S00E03.mkv"));
+        
+        // A regular episode first.
+        assert_eq!(Some(1), parse_season_number("Law.and.Order.Special.Victims.Unit.S01E02.mkv"));
+        assert_eq!("Law.and.Order.Special.Victims.Unit.", parse_show_name("Law.and.Order.Special.Victims.Unit.S01E02.mkv").unwrap());
+    }
+    
     #[test]
     fn test_episode_number_bad() {
         let actual = parse_episode_number("friends - yep.mp4");
@@ -0,0 +1,329 @@
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::batch::Step;
+use crate::episode::Episode;
+use crate::parsers::parse_season_number;
+use crate::sidecar::Sidecar;
+use crate::transfer::Mode;
+
+/// One planned file, with what was parsed from it.
+/// Only the paths are needed, so new files can be added by hand.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    #[serde(default)]
+    pub season: u32,
+    #[serde(default)]
+    pub episode: u32,
+    #[serde(default)]
+    pub last_episode: u32,
+    #[serde(default)]
+    pub title: String,
+    /// Which parser rule found the episode number.
+    #[serde(default)]
+    pub rule: String,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+impl Item {
+    /// The step for an episode, after any conflicts were resolved.
+    pub fn new(episode: &Episode, step: &Step) -> Item {
+        Item {
+            source: step.from.clone(),
+            destination: step.to.clone(),
+            season: episode.season,
+            episode: episode.episode,
+            last_episode: episode.last_episode,
+            title: episode.name.clone(),
+            rule: String::from(episode.rule),
+            warnings: warnings(episode),
+        }
+    }
+    
+    /// The step to run for this item.
+    /// Conflicts are resolved again when it's run.
+    pub fn step(&self) -> Step {
+        Step::new(&self.source, &self.destination)
+    }
+}
+
+/// A plan as it's written to file.
+#[derive(Serialize, Deserialize)]
+struct PlanFile {
+    version: u32,
+    mode: String,
+    files: Vec<Item>,
+    #[serde(default)]
+    warnings: Vec<String>,
+}
+
+/// Everything a run would do, without doing it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plan {
+    pub mode: Mode,
+    pub items: Vec<Item>,
+    /// Notes about the whole run, like conflicts that were resolved.
+    pub warnings: Vec<String>,
+}
+
+impl Plan {
+    /// Match up the final steps with their episodes.
+    /// Episodes that were skipped have no step, so aren't in the plan.
+    /// Sidecars are described by their episode.
+    pub fn new(mode: Mode, episodes: &[&Episode], sidecars: &[Sidecar], steps: &[Step], warnings: Vec<String>) -> Plan {
+        let items = steps.iter()
+            .filter_map(|step| {
+                let video = sidecars.iter()
+                    .find(|sidecar| sidecar.path == step.from)
+                    .map_or(&step.from, |sidecar| &sidecar.episode);
+                
+                let mut item = episodes.iter()
+                    .find(|episode| episode.path == *video)
+                    .map(|episode| Item::new(episode, step))?;
+                
+                if *video != step.from {
+                    item.rule = String::from("sidecar");
+                    item.warnings.clear();
+                }
+                
+                Some(item)
+            })
+            .collect();
+        
+        Plan {
+            mode,
+            items,
+            warnings,
+        }
+    }
+    
+    /// Read a plan, maybe one that was edited by hand.
+    pub fn parse(text: &str) -> Result<Plan, String> {
+        let file: PlanFile = serde_json::from_str(text)
+            .map_err(|e| e.to_string())?;
+        
+        if file.version != 1 {
+            return Err(format!("Plan version {} isn't supported.", file.version));
+        }
+        
+        let mode = Mode::parse(&file.mode)
+            .ok_or(format!("Unknown mode '{}'.", file.mode))?;
+        
+        Ok(Plan {
+            mode,
+            items: file.files,
+            warnings: file.warnings,
+        })
+    }
+    
+    /// Read a plan file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Plan, String> {
+        let text = fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("Failed to read {}: {}", path.as_ref().display(), e))?;
+        
+        Plan::parse(&text)
+            .map_err(|e| format!("Bad plan in {}\n{}", path.as_ref().display(), e))
+    }
+    
+    /// The steps to run, in order.
+    pub fn steps(&self) -> Vec<Step> {
+        self.items.iter().map(Item::step).collect()
+    }
+    
+    /// Write the plan as JSON.
+    pub fn to_json(&self) -> String {
+        let file = PlanFile {
+            version: 1,
+            mode: String::from(self.mode.name()),
+            files: self.items.clone(),
+            warnings: self.warnings.clone(),
+        };
+        
+        // Only paths that aren't unicode can fail.
+        serde_json::to_string_pretty(&file).unwrap_or_default()
+    }
+}
+
+/// Things about an episode worth a second look.
+fn warnings(episode: &Episode) -> Vec<String> {
+    let mut warnings = Vec::new();
+    
+    let file_name = episode.path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    
+    if let Some(season) = parse_season_number(&file_name) {
+        if season != episode.season {
+            warnings.push(format!("The file says season {}.", season));
+        }
+    }
+    
+    if episode.season == 0 {
+        warnings.push(String::from("A special, in season 0."));
+    }
+    
+    if episode.name.is_empty() {
+        warnings.push(String::from("No episode title."));
+    }
+    
+    warnings
+}
+
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use crate::cleaner::Cleaner;
+    use crate::conflict::{Policy, find_conflicts, resolve};
+    use crate::episode_factory::EpisodeFactory;
+    
+    #[test]
+    fn test_plan() {
+        let cleaner = Cleaner::new(vec!["lol"]);
+        let mut factory = EpisodeFactory::new("Friends", 1, &cleaner);
+        
+        factory.insert("tv/friends.1x01.the.one.mp4").unwrap();
+        factory.insert("tv/friends.s02e02.the.two.mp4").unwrap();
+        factory.insert("tv/friends.3of9.mp4").unwrap();
+        factory.insert("tv/friends.1x04.mp4").unwrap();
+        
+        let episodes = factory.get_all();
+        
+        // The last one was skipped.
+        let steps = vec![
+            Step::new("tv/friends.1x01.the.one.mp4", "tv/Friends S01E01 - The One.mp4"),
+            Step::new("tv/friends.s02e02.the.two.mp4", "tv/Friends S01E02 - The Two.mp4"),
+            Step::new("tv/friends.3of9.mp4", "tv/Friends S01E03.mp4"),
+            Step::new("tv/friends.1x01.the.one.en.srt", "tv/Friends S01E01 - The One.en.srt"),
+        ];
+        
+        let sidecars = vec![Sidecar {
+            path: PathBuf::from("tv/friends.1x01.the.one.en.srt"),
+            episode: PathBuf::from("tv/friends.1x01.the.one.mp4"),
+            suffix: String::from(".en"),
+            extension: String::from("srt"),
+        }];
+        
+        let plan = Plan::new(Mode::Rename, &episodes, &sidecars, &steps, vec![String::from("Skipped one.")]);
+        
+        assert_eq!(4, plan.items.len());
+        assert_eq!("-x-", plan.items[0].rule);
+        assert_eq!("The One", plan.items[0].title);
+        assert!(plan.items[0].warnings.is_empty());
+        assert_eq!("S--E--", plan.items[1].rule);
+        assert_eq!(vec!["The file says season 2."], plan.items[1].warnings);
+        assert_eq!("of", plan.items[2].rule);
+        assert_eq!(vec!["No episode title."], plan.items[2].warnings);
+        assert_eq!("sidecar", plan.items[3].rule);
+        assert_eq!("The One", plan.items[3].title);
+        assert_eq!(vec!["Skipped one."], plan.warnings);
+    }
+    
+    #[test]
+    fn test_to_json() {
+        let plan = Plan {
+            mode: Mode::Copy,
+            items: vec![Item {
+                source: PathBuf::from("tv/friends.1x01.mp4"),
+                destination: PathBuf::from("tv/Friends S01E01.mp4"),
+                season: 1,
+                episode: 1,
+                last_episode: 1,
+                title: String::new(),
+                rule: String::from("-x-"),
+                warnings: vec![String::from("No episode title.")],
+            }],
+            warnings: vec![],
+        };
+        
+        let expected = r#"{
+  "version": 1,
+  "mode": "copy",
+  "files": [
+    {
+      "source": "tv/friends.1x01.mp4",
+      "destination": "tv/Friends S01E01.mp4",
+      "season": 1,
+      "episode": 1,
+      "last_episode": 1,
+      "title": "",
+      "rule": "-x-",
+      "warnings": [
+        "No episode title."
+      ]
+    }
+  ],
+  "warnings": []
+}"#;
+        
+        assert_eq!(expected, plan.to_json());
+    }
+    
+    #[test]
+    fn test_parse() {
+        let plan = Plan {
+            mode: Mode::Hardlink,
+            items: vec![Item {
+                source: PathBuf::from("tv/friends.1x01.mp4"),
+                destination: PathBuf::from("tv/Friends S01E01.mp4"),
+                season: 1,
+                episode: 1,
+                last_episode: 2,
+                title: String::from("The One"),
+                rule: String::from("-x-"),
+                warnings: vec![String::from("Hmm.")],
+            }],
+            warnings: vec![String::from("Skipped one.")],
+        };
+        
+        assert_eq!(Ok(plan.clone()), Plan::parse(&plan.to_json()));
+    }
+    
+    #[test]
+    fn test_parse_replace() {
+        let dir = env::temp_dir().join("tv-rename-plan-replace");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        
+        fs::write(dir.join("one.mp4"), "a bigger episode").unwrap();
+        fs::write(dir.join("Show S01E01.mp4"), "old episode").unwrap();
+        
+        let text = format!(r#"{{
+            "version": 1,
+            "mode": "rename",
+            "files": [
+                {{ "source": {:?}, "destination": {:?}, "replace": true }}
+            ]
+        }}"#, dir.join("one.mp4"), dir.join("Show S01E01.mp4"));
+        
+        let plan = Plan::parse(&text).unwrap();
+        
+        // Set by hand, so it's checked again.
+        assert!(!plan.steps()[0].replace);
+        assert_eq!(vec![0], find_conflicts(&plan.steps(), Mode::Rename));
+        assert!(resolve(plan.steps(), Mode::Rename, Policy::Abort).is_err());
+    }
+    
+    #[test]
+    fn test_parse_errors() {
+        let errors = vec![
+            (r#"{"mode": "copy", "files": []}"#, "missing field `version` at line 1 column 29"),
+            (r#"{"version": 2, "mode": "copy", "files": []}"#, "Plan version 2 isn't supported."),
+            (r#"{"version": 1, "mode": "teleport", "files": []}"#, "Unknown mode 'teleport'."),
+            (r#"{"version": 1, "mode": "copy"}"#, "missing field `files` at line 1 column 30"),
+            (r#"{"version": 1, "mode": "copy", "files": [{"source": "a"}]}"#, "missing field `destination` at line 1 column 56"),
+        ];
+        
+        for (text, expected) in errors {
+            assert_eq!(Err(String::from(expected)), Plan::parse(text));
+        }
+    }
+}
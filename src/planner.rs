@@ -0,0 +1,227 @@
+
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+
+use crate::batch::Step;
+
+/// Order renames so no file lands on another before it has moved away.
+///
+/// Each rename is a link in a graph, from one file to the next file it
+/// would overwrite. Because every target is unique, the graph is only
+/// made of simple chains and cycles.
+/// - Chains (a -> b, b -> c) run from the end: b -> c, then a -> b.
+/// - Cycles (a -> b, b -> a) go through a temporary name.
+/// - Files that don't move are dropped.
+///
+/// Targets that aren't unique are refused by `Batch::validate`, but they
+/// still only run once here instead of going around forever.
+pub fn plan(steps: &[Step]) -> Vec<Step> {
+    let steps: Vec<&Step> = steps.iter()
+        .filter(|step| step.from != step.to)
+        .collect();
+    
+    // Which step moves a file out of the way.
+    let sources: HashMap<&PathBuf, usize> = steps.iter()
+        .enumerate()
+        .map(|(i, step)| (&step.from, i))
+        .collect();
+    
+    // Which step must happen before this one.
+    let next: Vec<Option<usize>> = steps.iter()
+        .map(|step| sources.get(&step.to).cloned())
+        .collect();
+    
+    let has_previous: HashSet<usize> = next.iter()
+        .filter_map(|next| *next)
+        .collect();
+    
+    let mut planned = Vec::new();
+    let mut visited = vec![false; steps.len()];
+    
+    // Chains start at a step nothing else is waiting on.
+    for start in 0..steps.len() {
+        if has_previous.contains(&start) {
+            continue;
+        }
+        
+        let mut chain = Vec::new();
+        let mut current = Some(start);
+        
+        while let Some(i) = current {
+            // Two steps with one target can lead back into this chain.
+            if visited[i] {
+                break;
+            }
+            
+            visited[i] = true;
+            chain.push(steps[i].clone());
+            current = next[i];
+        }
+        
+        planned.extend(chain.into_iter().rev());
+    }
+    
+    // Everything left is in a cycle.
+    for start in 0..steps.len() {
+        if visited[start] {
+            continue;
+        }
+        
+        let mut cycle = Vec::new();
+        let mut current = start;
+        
+        while !visited[current] {
+            visited[current] = true;
+            cycle.push(current);
+            current = next[current].unwrap();
+        }
+        
+        // Move the first aside, which frees up the last.
+        let first = steps[cycle[0]];
+        let temporary = temporary_path(&first.from);
+        
+        planned.push(Step::new(&first.from, &temporary));
+        
+        for i in cycle[1..].iter().rev() {
+            planned.push(steps[*i].clone());
+        }
+        
+        planned.push(Step::new(&temporary, &first.to));
+    }
+    
+    planned
+}
+
+/// A free name next to a file, to park it for a moment.
+pub fn temporary_path(path: &Path) -> PathBuf {
+    let name = path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    
+    let mut count = 0;
+    
+    loop {
+        let temporary = path.with_file_name(format!(".{}.tv-rename-{}", name, count));
+        
+        if !temporary.exists() {
+            return temporary;
+        }
+        
+        count += 1;
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    
+    fn step(from: &str, to: &str) -> Step {
+        Step::new(from, to)
+    }
+    
+    #[test]
+    fn test_independent() {
+        let steps = vec![
+            step("a.mkv", "one.mkv"),
+            step("b.mkv", "two.mkv"),
+        ];
+        
+        assert_eq!(steps, plan(&steps));
+    }
+    
+    #[test]
+    fn test_unchanged() {
+        let steps = vec![
+            step("a.mkv", "a.mkv"),
+            step("b.mkv", "two.mkv"),
+        ];
+        
+        assert_eq!(vec![step("b.mkv", "two.mkv")], plan(&steps));
+    }
+    
+    #[test]
+    fn test_chain() {
+        let steps = vec![
+            step("a.mkv", "b.mkv"),
+            step("b.mkv", "c.mkv"),
+            step("c.mkv", "d.mkv"),
+        ];
+        
+        let expected = vec![
+            step("c.mkv", "d.mkv"),
+            step("b.mkv", "c.mkv"),
+            step("a.mkv", "b.mkv"),
+        ];
+        
+        assert_eq!(expected, plan(&steps));
+    }
+    
+    #[test]
+    fn test_chain_unordered() {
+        let steps = vec![
+            step("b.mkv", "c.mkv"),
+            step("x.mkv", "y.mkv"),
+            step("a.mkv", "b.mkv"),
+        ];
+        
+        let expected = vec![
+            step("x.mkv", "y.mkv"),
+            step("b.mkv", "c.mkv"),
+            step("a.mkv", "b.mkv"),
+        ];
+        
+        assert_eq!(expected, plan(&steps));
+    }
+    
+    #[test]
+    fn test_swap() {
+        let steps = vec![
+            step("a.mkv", "b.mkv"),
+            step("b.mkv", "a.mkv"),
+        ];
+        
+        let expected = vec![
+            step("a.mkv", ".a.mkv.tv-rename-0"),
+            step("b.mkv", "a.mkv"),
+            step(".a.mkv.tv-rename-0", "b.mkv"),
+        ];
+        
+        assert_eq!(expected, plan(&steps));
+    }
+    
+    #[test]
+    fn test_rotation() {
+        let steps = vec![
+            step("a.mkv", "b.mkv"),
+            step("b.mkv", "c.mkv"),
+            step("c.mkv", "a.mkv"),
+        ];
+        
+        let expected = vec![
+            step("a.mkv", ".a.mkv.tv-rename-0"),
+            step("c.mkv", "a.mkv"),
+            step("b.mkv", "c.mkv"),
+            step(".a.mkv.tv-rename-0", "b.mkv"),
+        ];
+        
+        assert_eq!(expected, plan(&steps));
+    }
+    
+    #[test]
+    fn test_shared_target() {
+        let steps = vec![
+            step("b.mkv", "c.mkv"),
+            step("c.mkv", "a.mkv"),
+            step("a.mkv", "c.mkv"),
+        ];
+        
+        let planned = plan(&steps);
+        
+        assert_eq!(3, planned.len());
+        
+        for step in &steps {
+            assert!(planned.contains(step));
+        }
+    }
+}
@@ -0,0 +1,270 @@
+
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::cleaner::{Cleaner, Scope};
+use crate::episode::Episode;
+use crate::input::Input;
+use crate::parsers::parse_episode_name;
+
+/// An answer to the preview.
+#[derive(Debug, PartialEq)]
+pub enum Answer {
+    Yes,
+    No,
+    /// Change a file, by its place in the list.
+    Edit(usize),
+    /// Tidy the titles again, like after changing the rules.
+    Clean,
+}
+
+impl Answer {
+    /// Files are counted from 1, as they're shown.
+    pub fn parse(line: &str, count: usize) -> Result<Answer, String> {
+        match line.to_lowercase().as_ref() {
+            "y" | "yes" => Ok(Answer::Yes),
+            "n" | "no" => Ok(Answer::No),
+            "c" | "clean" => Ok(Answer::Clean),
+            line => match line.parse::<usize>() {
+                Ok(number) if number >= 1 && number <= count => Ok(Answer::Edit(number - 1)),
+                Ok(number) => Err(format!("There's no file {}.", number)),
+                Err(_) => Err(String::from("Type 'yes', 'no', a number or 'clean'.")),
+            },
+        }
+    }
+}
+
+/// What to change about a file.
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Episode,
+    Season,
+    Title,
+    Exclude,
+    Back,
+}
+
+impl Change {
+    pub fn parse(line: &str) -> Option<Change> {
+        match line.to_lowercase().as_ref() {
+            "e" | "episode" => Some(Change::Episode),
+            "s" | "season" => Some(Change::Season),
+            "t" | "title" => Some(Change::Title),
+            "x" | "exclude" => Some(Change::Exclude),
+            "" | "b" | "back" => Some(Change::Back),
+            _ => None,
+        }
+    }
+}
+
+/// Ask what to do with the preview.
+pub fn ask(input: &mut Input, count: usize) -> Answer {
+    loop {
+        match Answer::parse(&input.line("yes? "), count) {
+            Ok(answer) => return answer,
+            Err(err) => println!("{}", err),
+        }
+    }
+}
+
+/// Changes made to the preview.
+pub struct Review {
+    /// Titles that were typed in, so cleaning again leaves them alone.
+    titled: Vec<PathBuf>,
+}
+
+impl Review {
+    pub fn new() -> Review {
+        Review {
+            titled: Vec::new(),
+        }
+    }
+    
+    /// Change one file.
+    /// This gives back the episode if it was excluded.
+    pub fn edit(&mut self, input: &mut Input, episodes: &mut Vec<Episode>, index: usize) -> Option<Episode> {
+        let mut episode = episodes[index].clone();
+        
+        println!("{}", episode);
+        println!("Change the (e)pisode, (s)eason or (t)itle, e(x)clude it, or go (b)ack?");
+        
+        let change = loop {
+            match Change::parse(&input.line("change? ")) {
+                Some(change) => break change,
+                None => println!("Type 'episode', 'season', 'title', 'exclude' or 'back'."),
+            }
+        };
+        
+        match change {
+            Change::Episode => {
+                println!("Which episode is it?");
+                let number = input.number(episode.episode);
+                
+                // A multi-episode file keeps its length.
+                episode.last_episode = number + (episode.last_episode - episode.episode);
+                episode.episode = number;
+                
+                // The number isn't a date anymore.
+                episode.date = None;
+            },
+            Change::Season => {
+                println!("Which season is it?");
+                episode.season = input.number(episode.season);
+                episode.date = None;
+            },
+            Change::Title => {
+                println!("What's it called?");
+                episode.name = input.text(&episode.name);
+                self.titled.push(episode.path.clone());
+            },
+            Change::Exclude => return Some(episodes.remove(index)),
+            Change::Back => return None,
+        }
+        
+        match find_clash(episodes, index, &episode) {
+            Some(other) => {
+                println!("That's the same episode as {:?}, nothing was changed.",
+                    other.path.file_name().unwrap_or_default());
+            },
+            None => {
+                episodes[index] = episode;
+                
+                // Only within the folder, so shows aren't mixed together.
+                let folder = folder_range(episodes, index);
+                episodes[folder].sort();
+            },
+        }
+        
+        None
+    }
+    
+    /// Tidy the titles again, except for those typed in.
+    pub fn clean(&self, episodes: &mut [Episode], cleaner: &Cleaner) {
+        for episode in episodes.iter_mut().filter(|episode| !self.titled.contains(&episode.path)) {
+            let file_name = episode.path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            
+            episode.name = parse_episode_name(&file_name)
+                .map(|name| cleaner.clean(&name, Scope::EpisodeName))
+                .unwrap_or_default();
+        }
+    }
+}
+
+/// Another episode of the same show that a changed one would overlap.
+fn find_clash<'e>(episodes: &'e [Episode], index: usize, episode: &Episode) -> Option<&'e Episode> {
+    episodes.iter()
+        .enumerate()
+        .find(|(i, other)| {
+            *i != index &&
+            other.show_name == episode.show_name &&
+            other.overlaps(episode)
+        })
+        .map(|(_, other)| other)
+}
+
+/// The episodes from the same folder as this one, they're listed together.
+fn folder_range(episodes: &[Episode], index: usize) -> Range<usize> {
+    let folder = episodes[index].path.parent();
+    
+    let start = episodes[..index].iter()
+        .rposition(|other| other.path.parent() != folder)
+        .map_or(0, |i| i + 1);
+    
+    let end = episodes[index..].iter()
+        .position(|other| other.path.parent() != folder)
+        .map_or(episodes.len(), |i| index + i);
+    
+    start..end
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::episode_factory::EpisodeFactory;
+    
+    #[test]
+    fn test_answer() {
+        assert_eq!(Ok(Answer::Yes), Answer::parse("yes", 3));
+        assert_eq!(Ok(Answer::No), Answer::parse("N", 3));
+        assert_eq!(Ok(Answer::Clean), Answer::parse("clean", 3));
+        assert_eq!(Ok(Answer::Edit(0)), Answer::parse("1", 3));
+        assert_eq!(Ok(Answer::Edit(2)), Answer::parse("3", 3));
+        assert_eq!(Err(String::from("There's no file 4.")), Answer::parse("4", 3));
+        assert_eq!(Err(String::from("There's no file 0.")), Answer::parse("0", 3));
+        assert_eq!(Err(String::from("Type 'yes', 'no', a number or 'clean'.")), Answer::parse("maybe", 3));
+    }
+    
+    #[test]
+    fn test_change() {
+        assert_eq!(Some(Change::Episode), Change::parse("e"));
+        assert_eq!(Some(Change::Season), Change::parse("Season"));
+        assert_eq!(Some(Change::Title), Change::parse("t"));
+        assert_eq!(Some(Change::Exclude), Change::parse("x"));
+        assert_eq!(Some(Change::Back), Change::parse(""));
+        assert_eq!(None, Change::parse("delete"));
+    }
+    
+    #[test]
+    fn test_clean() {
+        let cleaner = Cleaner::new(vec!["lol"]);
+        let mut factory = EpisodeFactory::new("Friends", 1, &cleaner);
+        
+        factory.insert("friends.1x01.the.one.lol.mp4").unwrap();
+        factory.insert("friends.1x02.the.two.ftw.mp4").unwrap();
+        
+        let mut episodes: Vec<Episode> = factory.get_all().into_iter().cloned().collect();
+        episodes[0].name = String::from("Typed In");
+        
+        let mut review = Review::new();
+        review.titled.push(episodes[0].path.clone());
+        
+        // With a new rule.
+        review.clean(&mut episodes, &Cleaner::new(vec!["lol", "ftw"]));
+        
+        assert_eq!("Typed In", episodes[0].name);
+        assert_eq!("The Two", episodes[1].name);
+    }
+    
+    fn create_episodes(show_name: &str, folder: &str) -> Vec<Episode> {
+        let cleaner = Cleaner::new(vec!["lol"]);
+        let mut factory = EpisodeFactory::new(show_name, 1, &cleaner);
+        
+        factory.insert(format!("{}/show.1x01.mp4", folder)).unwrap();
+        factory.insert(format!("{}/show.1x02.mp4", folder)).unwrap();
+        factory.insert(format!("{}/show.1x03.mp4", folder)).unwrap();
+        
+        factory.get_all().into_iter().cloned().collect()
+    }
+    
+    #[test]
+    fn test_clash_shows() {
+        let mut episodes = create_episodes("Show A", "a");
+        episodes.extend(create_episodes("Show B", "b"));
+        
+        // Show A's first episode becomes the third.
+        let mut episode = episodes[0].clone();
+        episode.episode = 3;
+        episode.last_episode = 3;
+        
+        let clash = find_clash(&episodes, 0, &episode).unwrap();
+        assert_eq!(PathBuf::from("a/show.1x03.mp4"), clash.path);
+        
+        // Show B's own episodes don't count.
+        episodes.remove(2);
+        assert!(find_clash(&episodes, 0, &episode).is_none());
+    }
+    
+    #[test]
+    fn test_folder_range() {
+        let mut episodes = create_episodes("Show A", "a");
+        episodes.extend(create_episodes("Show B", "b"));
+        episodes.extend(create_episodes("Show C", "c"));
+        
+        assert_eq!(0..3, folder_range(&episodes, 0));
+        assert_eq!(3..6, folder_range(&episodes, 4));
+        assert_eq!(6..9, folder_range(&episodes, 8));
+    }
+}
@@ -0,0 +1,320 @@
+
+use std::path::{Path, PathBuf};
+
+use crate::episode::Episode;
+use crate::parsers::{parse_absolute_number, parse_air_date, parse_episode_number_rule, parse_season_number, parse_special};
+
+/// Extensions of files that belong to a video.
+pub const SIDECAR_EXTENSIONS: [&str; 12] = [
+    "srt",
+    "sub",
+    "idx",
+    "ass",
+    "ssa",
+    "vtt",
+    "smi",
+    "nfo",
+    "jpg",
+    "jpeg",
+    "png",
+    "tbn",
+];
+
+/// Subtitle tags, kept at the end of the name.
+const TAGS: [&str; 8] = ["forced", "sdh", "cc", "hi", "default", "full", "signs", "songs"];
+
+/// Languages, as names or ISO 639 codes.
+const LANGUAGES: [&str; 60] = [
+    "en", "eng", "english",
+    "fr", "fre", "fra", "french",
+    "de", "ger", "deu", "german",
+    "es", "spa", "spanish",
+    "it", "ita", "italian",
+    "pt", "por", "portuguese",
+    "nl", "dut", "nld", "dutch",
+    "sv", "swe", "swedish",
+    "no", "nor", "nob", "norwegian",
+    "da", "dan", "danish",
+    "fi", "fin", "finnish",
+    "pl", "pol", "polish",
+    "ru", "rus", "russian",
+    "ja", "jpn", "japanese",
+    "zh", "chi", "zho", "chinese",
+    "ko", "kor", "korean",
+    "ar", "ara", "arabic",
+    "he", "heb",
+    "tr", "tur",
+];
+
+/// Art for media servers, like 'name-thumb.jpg'.
+const ART: [&str; 4] = ["-thumb", "-poster", "-fanart", "-banner"];
+
+/// A file that follows a video, like subtitles.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sidecar {
+    pub path: PathBuf,
+    /// The video it belongs to.
+    pub episode: PathBuf,
+    /// Whatever is after the name, like '.forced.eng' or '-thumb'.
+    pub suffix: String,
+    pub extension: String,
+}
+
+impl Sidecar {
+    /// Find the episode a sidecar belongs to.
+    /// A sidecar named like the video keeps everything after the name.
+    /// Otherwise it's matched by episode number (or date), keeping any tags.
+    pub fn find<P: AsRef<Path>>(path: P, episodes: &[&Episode]) -> Option<Sidecar> {
+        let path = path.as_ref();
+        let stem = file_stem(path);
+        let extension = path.extension()?.to_string_lossy().to_string();
+        
+        // The longest name wins, in case one is the start of another.
+        let by_name = episodes.iter()
+            .filter(|episode| episode.path.parent() == path.parent())
+            .filter_map(|episode| {
+                let video = file_stem(&episode.path);
+                let rest = stem.get(video.len()..)?;
+                
+                if stem.starts_with(&video) &&
+                    (rest.is_empty() || rest.starts_with(['.', '-', '_'])) {
+                    Some((video.len(), episode, String::from(rest)))
+                }
+                else {
+                    None
+                }
+            })
+            .max_by_key(|(len, _, _)| *len);
+        
+        if let Some((_, episode, suffix)) = by_name {
+            return Some(Sidecar {
+                path: PathBuf::from(path),
+                episode: episode.path.clone(),
+                suffix,
+                extension,
+            });
+        }
+        
+        let file_name = path.file_name()?.to_string_lossy().to_string();
+        // Daily shows are numbered by their date, and specials like 'SP01'
+        // might only have their own number.
+        let first = match parse_episode_number_rule(&file_name) {
+            Some((first, _, _)) => first,
+            None => parse_air_date(&file_name)
+                .map(|(_, month, day)| month * 100 + day)
+                .or_else(|| parse_special(&file_name).flatten())?,
+        };
+        let season = parse_season_number(&file_name);
+        
+        // Anime numbers are matched before they were split into seasons.
+        let absolute = parse_absolute_number(&file_name);
+        
+        let mut matches = episodes.iter()
+            .filter(|episode| episode.path.parent() == path.parent())
+            .filter(|episode| match absolute {
+                Some(_) => episode.absolute == absolute,
+                None => (episode.episode..=episode.last_episode).contains(&first),
+            })
+            .filter(|episode| {
+                let video = file_stem(&episode.path);
+                season.is_none() || parse_season_number(&video) == season
+            });
+        
+        // Only if there's no doubt.
+        match (matches.next(), matches.next()) {
+            (Some(episode), None) => Some(Sidecar {
+                path: PathBuf::from(path),
+                episode: episode.path.clone(),
+                suffix: tags(&stem),
+                extension,
+            }),
+            _ => None,
+        }
+    }
+    
+    /// The new path, next to the new video.
+    pub fn destination(&self, episode: &Episode) -> PathBuf {
+        self.follow(&episode.destination())
+    }
+    
+    /// The new path, next to wherever the video ends up.
+    pub fn follow(&self, video: &Path) -> PathBuf {
+        let name = format!("{}{}.{}", file_stem(video), self.suffix, self.extension);
+        video.with_file_name(name)
+    }
+}
+
+/// Whether a file might belong to a video.
+pub fn is_sidecar<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| SIDECAR_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// The tags at the end of a name, like '.forced.eng' or '.pt-BR.sdh'.
+fn tags(stem: &str) -> String {
+    let lower = stem.to_lowercase();
+    
+    if let Some(art) = ART.iter().find(|art| lower.ends_with(*art)) {
+        return String::from(&stem[stem.len() - art.len()..]);
+    }
+    
+    let tokens: Vec<&str> = stem.split('.').collect();
+    
+    let count = tokens.iter()
+        .rev()
+        .take_while(|token| is_tag(token))
+        .count();
+    
+    // The whole name isn't a tag.
+    let count = count.min(tokens.len() - 1);
+    
+    tokens[tokens.len() - count..].iter()
+        .map(|token| format!(".{}", token))
+        .collect()
+}
+
+/// A language (maybe with a region, like 'pt-BR') or a subtitle tag.
+fn is_tag(token: &str) -> bool {
+    let token = token.to_lowercase();
+    let language = token.split(['-', '_']).next().unwrap_or("");
+    
+    TAGS.contains(&token.as_str()) || LANGUAGES.contains(&language)
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cleaner::Cleaner;
+    use crate::episode_factory::EpisodeFactory;
+    
+    fn find(path: &str) -> Option<PathBuf> {
+        let cleaner = Cleaner::new(vec!["lol"]);
+        let mut factory = EpisodeFactory::new("Show", 1, &cleaner);
+        
+        factory.insert("tv/Show.S01E01.Pilot.mkv").unwrap();
+        factory.insert("tv/Show.S01E02.Two.mkv").unwrap();
+        factory.insert("tv/Show.S01E12.Twelve.mkv").unwrap();
+        
+        let episodes = factory.get_all();
+        let sidecar = Sidecar::find(path, &episodes)?;
+        let episode = episodes.iter().find(|ep| ep.path == sidecar.episode)?;
+        
+        Some(sidecar.destination(episode))
+    }
+    
+    #[test]
+    fn test_by_name() {
+        assert_eq!(Some(PathBuf::from("tv/Show S01E02 - Two.srt")), find("tv/Show.S01E02.Two.srt"));
+        assert_eq!(Some(PathBuf::from("tv/Show S01E02 - Two.en.srt")), find("tv/Show.S01E02.Two.en.srt"));
+        assert_eq!(Some(PathBuf::from("tv/Show S01E02 - Two.idx")), find("tv/Show.S01E02.Two.idx"));
+        assert_eq!(Some(PathBuf::from("tv/Show S01E02 - Two.nfo")), find("tv/Show.S01E02.Two.nfo"));
+        assert_eq!(Some(PathBuf::from("tv/Show S01E02 - Two-thumb.jpg")), find("tv/Show.S01E02.Two-thumb.jpg"));
+    }
+    
+    #[test]
+    fn test_by_episode() {
+        assert_eq!(Some(PathBuf::from("tv/Show S01E02 - Two.en.srt")), find("tv/Show.S01E02.en.srt"));
+        assert_eq!(Some(PathBuf::from("tv/Show S01E02 - Two.forced.eng.srt")), find("tv/Show.S01E02.forced.eng.srt"));
+        assert_eq!(Some(PathBuf::from("tv/Show S01E02 - Two.pt-BR.sdh.srt")), find("tv/show.s01e02.pt-BR.sdh.srt"));
+        assert_eq!(Some(PathBuf::from("tv/Show S01E01 - Pilot-thumb.jpg")), find("tv/Show.S01E01-thumb.jpg"));
+        assert_eq!(Some(PathBuf::from("tv/Show S01E12 - Twelve.srt")), find("tv/Show.S01E12.srt"));
+    }
+    
+    #[test]
+    fn test_by_date() {
+        let cleaner = Cleaner::new(vec!["lol"]);
+        let mut factory = EpisodeFactory::new("Show", 1, &cleaner);
+        
+        factory.insert("tv/Show.2019.03.14.Guest.Name.mkv").unwrap();
+        factory.insert("tv/Show.2019.03.15.Other.Guest.mkv").unwrap();
+        
+        let episodes = factory.get_all();
+        let sidecar = Sidecar::find("tv/Show.2019.03.14.en.srt", &episodes).unwrap();
+        
+        assert_eq!(PathBuf::from("tv/Show - 2019-03-14 - Guest Name.en.srt"), sidecar.destination(episodes[0]));
+    }
+    
+    #[test]
+    fn test_by_absolute() {
+        let cleaner = Cleaner::new(vec!["lol"]);
+        let mut factory = EpisodeFactory::new("Show", 1, &cleaner);
+        factory.set_episodes_per_season(vec![50, 51, 52]);
+        
+        factory.insert("tv/[Group] Show - 36 [1080p].mkv").unwrap();
+        factory.insert("tv/[Group] Show - 137 [1080p].mkv").unwrap();
+        
+        let episodes = factory.get_all();
+        
+        let sidecar = Sidecar::find("tv/[Group] Show - 137.en.ass", &episodes).unwrap();
+        assert_eq!(PathBuf::from("tv/[Group] Show - 137 [1080p].mkv"), sidecar.episode);
+        
+        // Not S03E36.
+        let sidecar = Sidecar::find("tv/[Group] Show - 36.en.ass", &episodes).unwrap();
+        assert_eq!(PathBuf::from("tv/[Group] Show - 36 [1080p].mkv"), sidecar.episode);
+    }
+    
+    #[test]
+    fn test_by_special() {
+        let cleaner = Cleaner::new(vec!["lol"]);
+        let mut factory = EpisodeFactory::new("Show", 1, &cleaner);
+        
+        factory.insert("tv/Show.S01E01.Pilot.mkv").unwrap();
+        factory.insert("tv/Show.SP01.The.Movie.mkv").unwrap();
+        
+        let episodes = factory.get_all();
+        
+        // Not S01E01.
+        let sidecar = Sidecar::find("tv/Show.SP01.en.srt", &episodes).unwrap();
+        assert_eq!(PathBuf::from("tv/Show.SP01.The.Movie.mkv"), sidecar.episode);
+        assert_eq!(".en", sidecar.suffix);
+    }
+    
+    #[test]
+    fn test_multi_episode() {
+        let cleaner = Cleaner::new(vec!["lol"]);
+        let mut factory = EpisodeFactory::new("Show", 1, &cleaner);
+        
+        factory.insert("tv/Show.S01E05E06.Five.mkv").unwrap();
+        factory.insert("tv/Show.S01E07.Seven.mkv").unwrap();
+        
+        let episodes = factory.get_all();
+        
+        for path in &["tv/Show.S01E05.en.srt", "tv/Show.S01E06.en.srt"] {
+            let sidecar = Sidecar::find(path, &episodes).unwrap();
+            assert_eq!(PathBuf::from("tv/Show.S01E05E06.Five.mkv"), sidecar.episode);
+        }
+    }
+    
+    #[test]
+    fn test_not_found() {
+        assert_eq!(None, find("tv/tvshow.nfo"));
+        assert_eq!(None, find("tv/Show.S01E03.en.srt"));
+        assert_eq!(None, find("tv/Show.S02E02.en.srt"));
+        assert_eq!(None, find("other/Show.S01E02.Two.srt"));
+    }
+    
+    #[test]
+    fn test_is_sidecar() {
+        assert!(is_sidecar("Show.S01E02.en.SRT"));
+        assert!(is_sidecar("Show.S01E02.sub"));
+        assert!(!is_sidecar("Show.S01E02.mkv"));
+        assert!(!is_sidecar("README"));
+    }
+    
+    #[test]
+    fn test_tags() {
+        assert_eq!(".en", tags("Show.S01E02.en"));
+        assert_eq!(".Forced.ENG", tags("Show.S01E02.Forced.ENG"));
+        assert_eq!("", tags("Show.S01E02.The.Way"));
+        assert_eq!("-thumb", tags("Show.S01E02-thumb"));
+        assert_eq!("", tags("en"));
+    }
+}
@@ -0,0 +1,581 @@
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+
+use crate::config::get_config_path;
+use crate::episode::Episode;
+
+/// Default in-built template, the classic 'Show S01E01 - Title.ext'.
+pub const DEFAULT_TEMPLATE: &str = "{show}[ ({year})] {id}[ - {title}].{ext}";
+
+/// Daily shows are named by date, 'Show - 2019-03-14 - Title.ext'.
+pub const DAILY_TEMPLATE: &str = "{show}[ ({year})] - {date}[ - {title}].{ext}";
+
+lazy_static! {
+    // Names for daily episodes, parsed once.
+    static ref DAILY: Template = Template::parse(DAILY_TEMPLATE).unwrap();
+}
+
+/// In-built templates for media servers, selected by name.
+pub const PRESETS: [(&str, &str); 4] = [
+    ("plex", "{show}[ ({year})]/Season {season:02}/{show}[ ({year})] - {id}[ - {title}].{ext}"),
+    ("jellyfin", "{show}[ ({year})]/Season {season:02}/{show}[ ({year})] {id}[ - {title}].{ext}"),
+    ("kodi", "{show}[ ({year})]/Season {season:02}/{show} {id}[ - {title}].{ext}"),
+    ("emby", "{show}[ ({year})]/Season {season:02}/{show}[ ({year})] - {id}[ - {title}].{ext}"),
+];
+
+/// Help text written above the template in a fresh config file.
+const TEMPLATE_HELP: &str = "\
+# Output file name template.
+#
+# Variables: {show} {year} {season} {episode} {absolute} {id} {date} {title} {ext}
+# Numbers can be zero-padded, like {season:02} or {episode:03}.
+# Sections in [ ] are left out when a variable inside them is empty.
+# Write {{ }} [[ ]] for literal braces or brackets.
+# A '/' creates folders, like {show}/Season {season}/{id}.{ext}
+#
+# Eg. {show} - {season}x{episode:02}[ - {title}].{ext}
+#
+# Or use a preset: @plex, @jellyfin, @kodi, @emby
+";
+
+/// Values that can be written into a template.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Variable {
+    Show,
+    Year,
+    Season,
+    Episode,
+    Absolute,
+    Id,
+    Date,
+    Title,
+    Extension,
+}
+
+impl Variable {
+    fn parse(name: &str) -> Option<Variable> {
+        match name {
+            "show" => Some(Variable::Show),
+            "year" => Some(Variable::Year),
+            "season" => Some(Variable::Season),
+            "episode" => Some(Variable::Episode),
+            "absolute" => Some(Variable::Absolute),
+            "id" => Some(Variable::Id),
+            "date" => Some(Variable::Date),
+            "title" => Some(Variable::Title),
+            "ext" => Some(Variable::Extension),
+            _ => None,
+        }
+    }
+    
+    /// Only numbers can be padded.
+    fn is_number(&self) -> bool {
+        matches!(self, Variable::Season | Variable::Episode | Variable::Absolute)
+    }
+    
+    /// Get the value from an episode, padding numbers to the given width.
+    /// Path separators are replaced so only the template can create folders.
+    fn value(&self, episode: &Episode, width: usize) -> String {
+        match self {
+            Variable::Show => clean_separators(&episode.show_name),
+            Variable::Year => episode.year.map(|year| year.to_string()).unwrap_or_default(),
+            Variable::Season => format!("{:01$}", episode.season, width),
+            Variable::Episode => {
+                if episode.last_episode > episode.episode {
+                    format!("{:02$}-{:02$}", episode.episode, episode.last_episode, width)
+                }
+                else {
+                    format!("{:01$}", episode.episode, width)
+                }
+            },
+            Variable::Absolute => {
+                episode.absolute.map(|absolute| format!("{:01$}", absolute, width)).unwrap_or_default()
+            },
+            Variable::Id => episode.identifier(),
+            Variable::Date => episode.date.map(|date| date.to_string()).unwrap_or_default(),
+            Variable::Title => clean_separators(&episode.name),
+            Variable::Extension => clean_separators(&episode.extension),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Variable(Variable, usize),
+    Optional(Vec<Part>),
+}
+
+/// Output naming template.
+/// - Variables are written as `{show}`, `{season:02}`, etc.
+/// - Sections in `[ ]` are dropped if any variable inside them is empty.
+/// - Braces and brackets are escaped by doubling them, like `{{` or `]]`.
+#[derive(Clone, Debug)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parse a template.
+    /// Errors describe the column of the problem.
+    pub fn parse<S: AsRef<str>>(text: S) -> Result<Template, String> {
+        let mut chars = text.as_ref().chars().enumerate().peekable();
+        
+        let mut parts: Vec<Part> = Vec::new();
+        let mut optional: Option<(usize, Vec<Part>)> = None;
+        let mut literal = String::new();
+        
+        while let Some((i, c)) = chars.next() {
+            let column = i + 1;
+            
+            // Escaped characters.
+            if "{}[]".contains(c) && chars.peek().map(|&(_, next)| next) == Some(c) {
+                chars.next();
+                literal.push(c);
+                continue;
+            }
+            
+            match c {
+                '{' => {
+                    let mut name = String::new();
+                    
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, c)) => name.push(c),
+                            None => return Err(format!("column {}: Unclosed '{{'.", column)),
+                        }
+                    }
+                    
+                    let part = parse_variable(&name)
+                        .map_err(|err| format!("column {}: {}", column, err))?;
+                    
+                    let target = current(&mut parts, &mut optional);
+                    flush(target, &mut literal);
+                    target.push(part);
+                },
+                '}' => {
+                    return Err(format!("column {}: Unmatched '}}'.", column));
+                },
+                '[' => {
+                    if optional.is_some() {
+                        return Err(format!("column {}: Sections cannot be nested.", column));
+                    }
+                    
+                    flush(&mut parts, &mut literal);
+                    optional = Some((column, Vec::new()));
+                },
+                ']' => {
+                    match optional.take() {
+                        Some((_, mut section)) => {
+                            flush(&mut section, &mut literal);
+                            parts.push(Part::Optional(section));
+                        },
+                        None => return Err(format!("column {}: Unmatched ']'.", column)),
+                    }
+                },
+                c => literal.push(c),
+            }
+        }
+        
+        if let Some((column, _)) = optional {
+            return Err(format!("column {}: Unclosed '['.", column));
+        }
+        
+        flush(&mut parts, &mut literal);
+        
+        let template = Template {
+            parts,
+        };
+        
+        // Without these every file would collide or lose its type.
+        if !template.contains(Variable::Extension) {
+            return Err(String::from("Template must include {ext}."));
+        }
+        
+        if !template.contains(Variable::Episode) && !template.contains(Variable::Id)
+            && !template.contains(Variable::Date) {
+            return Err(String::from("Template must include {episode}, {id} or {date}."));
+        }
+        
+        Ok(template)
+    }
+    
+    /// Get an in-built preset by name.
+    pub fn preset(name: &str) -> Result<Template, String> {
+        let name = name.trim().to_lowercase();
+        
+        match PRESETS.iter().find(|(preset, _)| *preset == name) {
+            Some((_, template)) => Template::parse(template),
+            None => {
+                let names: Vec<&str> = PRESETS.iter().map(|(preset, _)| *preset).collect();
+                Err(format!("Unknown preset '{}', try one of: {}", name, names.join(", ")))
+            },
+        }
+    }
+    
+    /// Load a template from file.
+    /// This is the first line that isn't blank or a '#' comment.
+    /// A line like '@plex' selects a preset.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Template, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read template: {}", err))?;
+        
+        let line = contents.lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty() && !line.starts_with('#'));
+        
+        match line {
+            Some(line) if line.starts_with('@') => Template::preset(&line[1..]),
+            Some(line) => Template::parse(line),
+            None => Ok(Template::default()),
+        }
+    }
+    
+    /// Write this template for an episode.
+    pub fn render(&self, episode: &Episode) -> String {
+        render_parts(&self.parts, episode, false).unwrap_or_default()
+    }
+    
+    /// Write this template for a daily show.
+    /// Unless it has a {date} already, the file is named by the daily
+    /// template, inside any folders of this one.
+    pub fn render_daily(&self, episode: &Episode) -> String {
+        let path = self.render(episode);
+        
+        if self.contains(Variable::Date) {
+            return path;
+        }
+        
+        let name = DAILY.render(episode);
+        
+        match path.rfind('/') {
+            Some(i) => format!("{}/{}", &path[..i], name),
+            None => name,
+        }
+    }
+    
+    /// Whether this template creates its own folders.
+    pub fn has_folders(&self) -> bool {
+        self.parts.iter().any(|part| match part {
+            Part::Text(text) => text.contains('/'),
+            Part::Optional(section) => section.iter().any(|part| match part {
+                Part::Text(text) => text.contains('/'),
+                _ => false,
+            }),
+            _ => false,
+        })
+    }
+    
+    /// Whether this template uses a variable.
+    fn contains(&self, variable: Variable) -> bool {
+        self.parts.iter().any(|part| match part {
+            Part::Variable(found, _) => *found == variable,
+            Part::Optional(section) => section.iter().any(|part| match part {
+                Part::Variable(found, _) => *found == variable,
+                _ => false,
+            }),
+            _ => false,
+        })
+    }
+}
+
+impl Default for Template {
+    fn default() -> Template {
+        Template::parse(DEFAULT_TEMPLATE).unwrap()
+    }
+}
+
+/// Replace path separators in a value.
+fn clean_separators(value: &str) -> String {
+    value.replace(['/', '\\'], "-")
+}
+
+/// The parts list currently being written to.
+fn current<'a>(parts: &'a mut Vec<Part>, optional: &'a mut Option<(usize, Vec<Part>)>) -> &'a mut Vec<Part> {
+    match optional {
+        Some((_, section)) => section,
+        None => parts,
+    }
+}
+
+/// Push any pending text as a part.
+fn flush(parts: &mut Vec<Part>, literal: &mut String) {
+    if !literal.is_empty() {
+        parts.push(Part::Text(mem::take(literal)));
+    }
+}
+
+/// Parse the 'name:width' inside a variable.
+fn parse_variable(text: &str) -> Result<Part, String> {
+    let mut split = text.splitn(2, ':');
+    let name = split.next().unwrap_or("").trim();
+    
+    let variable = match Variable::parse(name) {
+        Some(variable) => variable,
+        None => return Err(format!("Unknown variable '{}'.", name)),
+    };
+    
+    let width = match split.next() {
+        Some(width) => {
+            if !variable.is_number() {
+                return Err(format!("Variable '{}' is not a number.", name));
+            }
+            
+            match width.trim().parse::<usize>() {
+                Ok(width) => width,
+                Err(_) => return Err(format!("Bad width '{}' for '{}'.", width, name)),
+            }
+        },
+        None => 0,
+    };
+    
+    Ok(Part::Variable(variable, width))
+}
+
+/// Render a list of parts.
+/// When strict, any empty variable blanks the whole list.
+fn render_parts(parts: &[Part], episode: &Episode, strict: bool) -> Option<String> {
+    let mut output = String::new();
+    
+    for part in parts {
+        match part {
+            Part::Text(text) => output.push_str(text),
+            Part::Variable(variable, width) => {
+                let value = variable.value(episode, *width);
+                
+                if strict && value.is_empty() {
+                    return None;
+                }
+                
+                output.push_str(&value);
+            },
+            Part::Optional(section) => {
+                if let Some(value) = render_parts(section, episode, true) {
+                    output.push_str(&value);
+                }
+            },
+        }
+    }
+    
+    Some(output)
+}
+
+/// Write the default template to file.
+pub fn write_template<P: AsRef<Path>>(path: P) -> Result<(), io::Error> {
+    let mut file = File::create(path)?;
+    writeln!(file, "{}{}", TEMPLATE_HELP, DEFAULT_TEMPLATE)
+}
+
+/// Get the template path.
+pub fn get_template_path() -> Result<PathBuf, &'static str> {
+    let path = get_config_path("template.txt")?;
+    
+    // Write a fresh file if it doesn't already exist.
+    if !path.exists() && write_template(&path).is_err() {
+        return Err("Failed to write template file.");
+    }
+    
+    Ok(path)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::rc::Rc;
+    use crate::episode::AirDate;
+    
+    fn create_episode(name: &str) -> Episode {
+        Episode {
+            path: PathBuf::from("one/two/three.mp4"),
+            episode: 20,
+            last_episode: 20,
+            season: 8,
+            name: String::from(name),
+            show_name: String::from("Friends"),
+            year: None,
+            extension: String::from("mp4"),
+            template: Rc::new(Template::default()),
+            library: None,
+            rule: "S--E--",
+            date: None,
+            absolute: None,
+        }
+    }
+    
+    #[test]
+    fn test_default() {
+        let template = Template::default();
+        
+        let actual = template.render(&create_episode("The One With The Baby Shower"));
+        let expected = "Friends S08E20 - The One With The Baby Shower.mp4";
+        assert_eq!(expected, actual);
+        
+        let actual = template.render(&create_episode(""));
+        let expected = "Friends S08E20.mp4";
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_default_year() {
+        let mut episode = create_episode("The One");
+        episode.year = Some(1994);
+        
+        let actual = Template::default().render(&episode);
+        let expected = "Friends (1994) S08E20 - The One.mp4";
+        
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_presets() {
+        let mut episode = create_episode("The One");
+        episode.year = Some(1994);
+        
+        let expected = vec![
+            ("plex", "Friends (1994)/Season 08/Friends (1994) - S08E20 - The One.mp4"),
+            ("jellyfin", "Friends (1994)/Season 08/Friends (1994) S08E20 - The One.mp4"),
+            ("kodi", "Friends (1994)/Season 08/Friends S08E20 - The One.mp4"),
+            ("Emby", "Friends (1994)/Season 08/Friends (1994) - S08E20 - The One.mp4"),
+        ];
+        
+        for (preset, expected) in expected {
+            assert_eq!(expected, Template::preset(preset).unwrap().render(&episode));
+        }
+        
+        // Without a year or title.
+        let actual = Template::preset("plex").unwrap().render(&create_episode(""));
+        let expected = "Friends/Season 08/Friends - S08E20.mp4";
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_has_folders() {
+        assert!(!Template::default().has_folders());
+        assert!(Template::preset("plex").unwrap().has_folders());
+    }
+    
+    #[test]
+    fn test_preset_bad() {
+        let actual = Template::preset("winamp").err();
+        let expected = "Unknown preset 'winamp', try one of: plex, jellyfin, kodi, emby";
+        assert_eq!(Some(String::from(expected)), actual);
+    }
+    
+    #[test]
+    fn test_separators() {
+        let mut episode = create_episode("Either/Or");
+        episode.show_name = String::from("AC/DC");
+        
+        let actual = Template::preset("plex").unwrap().render(&episode);
+        let expected = "AC-DC/Season 08/AC-DC - S08E20 - Either-Or.mp4";
+        
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_custom() {
+        let template = Template::parse("{show} - {season}x{episode:02} - {title}.{ext}").unwrap();
+        
+        let actual = template.render(&create_episode("The One"));
+        let expected = "Friends - 8x20 - The One.mp4";
+        
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_date() {
+        let mut episode = create_episode("The One");
+        
+        // Left out when it's not a daily show.
+        let template = Template::parse("{show}[ {date}] {id}.{ext}").unwrap();
+        assert_eq!("Friends S08E20.mp4", template.render(&episode));
+        
+        episode.date = Some(AirDate { year: 2019, month: 3, day: 14 });
+        
+        let template = Template::parse("{date} {show}.{ext}").unwrap();
+        assert_eq!("2019-03-14 Friends.mp4", template.render_daily(&episode));
+        
+        let template = Template::parse("{show}/{id}.{ext}").unwrap();
+        assert_eq!("Friends/Friends - 2019-03-14 - The One.mp4", template.render_daily(&episode));
+    }
+    
+    #[test]
+    fn test_absolute() {
+        let mut episode = create_episode("The One");
+        let template = Template::parse("{show} {id}[ ({absolute:03})].{ext}").unwrap();
+        
+        // Left out when it's not anime.
+        assert_eq!("Friends S08E20.mp4", template.render(&episode));
+        
+        episode.absolute = Some(137);
+        assert_eq!("Friends S08E20 (137).mp4", template.render(&episode));
+    }
+    
+    #[test]
+    fn test_padding() {
+        let template = Template::parse("{season:03}.{episode:04}.{ext}").unwrap();
+        
+        let mut episode = create_episode("");
+        assert_eq!("008.0020.mp4", template.render(&episode));
+        
+        episode.last_episode = 21;
+        assert_eq!("008.0020-0021.mp4", template.render(&episode));
+    }
+    
+    #[test]
+    fn test_escapes() {
+        let template = Template::parse("{{{show}}} [[{id}]].{ext}").unwrap();
+        
+        let actual = template.render(&create_episode(""));
+        let expected = "{Friends} [S08E20].mp4";
+        
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_errors() {
+        let errors = vec![
+            ("{show} {nope}.{ext}", "column 8: Unknown variable 'nope'."),
+            ("{show} {id", "column 8: Unclosed '{'."),
+            ("{show}} {id}.{ext}", "column 7: Unmatched '}'."),
+            ("{show:02} {id}.{ext}", "column 1: Variable 'show' is not a number."),
+            ("{episode:x}.{ext}", "column 1: Bad width 'x' for 'episode'."),
+            ("{id}[ - [{title}]].{ext}", "column 9: Sections cannot be nested."),
+            ("{id}[ - {title}.{ext}", "column 5: Unclosed '['."),
+            ("{id} - {title}].{ext}", "column 15: Unmatched ']'."),
+            ("{show} {id}", "Template must include {ext}."),
+            ("{show}.{ext}", "Template must include {episode}, {id} or {date}."),
+        ];
+        
+        for (template, expected) in errors {
+            assert_eq!(Some(String::from(expected)), Template::parse(template).err());
+        }
+    }
+    
+    #[test]
+    fn test_load() {
+        let template = Template::load(Path::new("test/template.txt")).unwrap();
+        
+        let actual = template.render(&create_episode("The One"));
+        let expected = "Friends - 8x20 - The One.mp4";
+        
+        assert_eq!(expected, actual);
+    }
+    
+    #[test]
+    fn test_load_preset() {
+        let template = Template::load(Path::new("test/template-preset.txt")).unwrap();
+        
+        let actual = template.render(&create_episode("The One"));
+        let expected = "Friends/Season 08/Friends S08E20 - The One.mp4";
+        
+        assert_eq!(expected, actual);
+    }
+}
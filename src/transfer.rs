@@ -0,0 +1,464 @@
+
+use std::fmt;
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Copy buffer size, also how often progress is reported.
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Progress callback for copies, given (copied, total) bytes.
+type Progress = fn(u64, u64) -> ();
+
+/// What to do with each file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Rename,
+    Copy,
+    Hardlink,
+    Symlink,
+    /// A symlink with a path relative to the link, so it survives the
+    /// whole library being moved.
+    RelativeSymlink,
+}
+
+impl Mode {
+    /// Get a mode by name, as given on the command line.
+    pub fn parse(name: &str) -> Option<Mode> {
+        match name {
+            "rename" | "move" => Some(Mode::Rename),
+            "copy" => Some(Mode::Copy),
+            "hardlink" => Some(Mode::Hardlink),
+            "symlink" => Some(Mode::Symlink),
+            "relative-symlink" => Some(Mode::RelativeSymlink),
+            _ => None,
+        }
+    }
+    
+    /// The command line name of a mode.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Rename => "rename",
+            Mode::Copy => "copy",
+            Mode::Hardlink => "hardlink",
+            Mode::Symlink => "symlink",
+            Mode::RelativeSymlink => "relative-symlink",
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Rename => "rename",
+            Mode::Copy => "copy",
+            Mode::Hardlink => "hard link",
+            Mode::Symlink => "symlink",
+            Mode::RelativeSymlink => "relative symlink",
+        })
+    }
+}
+
+/// How a copied file is checked before the original is removed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verify {
+    /// Compare file sizes.
+    Size,
+    /// Compare every byte, slower but catches corrupt copies.
+    Contents,
+}
+
+/// Moves, copies or links files about.
+pub struct Transfer {
+    mode: Mode,
+    verify: Verify,
+    progress: Progress,
+}
+
+impl Transfer {
+    
+    pub fn new(mode: Mode, verify: Verify, progress: Progress) -> Transfer {
+        Transfer {
+            mode,
+            verify,
+            progress,
+        }
+    }
+    
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+    
+    /// Put a file at the new path, according to the mode.
+    pub fn apply(&self, from: &Path, to: &Path) -> io::Result<()> {
+        match self.mode {
+            Mode::Rename => self.move_file(from, to),
+            Mode::Copy => self.copy_file(from, to),
+            Mode::Hardlink => fs::hard_link(from, to),
+            Mode::Symlink => {
+                symlink(&from.canonicalize()?, to)
+            },
+            Mode::RelativeSymlink => {
+                let from = from.canonicalize()?;
+                let parent = to.parent()
+                    .unwrap_or(Path::new("."))
+                    .canonicalize()?;
+                
+                symlink(&relative_path(&parent, &from), to)
+            },
+        }
+    }
+    
+    /// Take back an 'apply', moving the file back or removing the copy/link.
+    pub fn revert(&self, from: &Path, to: &Path) -> io::Result<()> {
+        match self.mode {
+            Mode::Rename => self.move_file(to, from),
+            // Everything else left the original alone.
+            _ => fs::remove_file(to),
+        }
+    }
+    
+    /// Move a file.
+    /// Renames that cross file systems fall back to copy-verify-delete.
+    pub fn move_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        match fs::rename(from, to) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                if is_cross_device(&err) {
+                    self.copy_file(from, to)?;
+                    
+                    // Don't leave two of the file behind.
+                    fs::remove_file(from).inspect_err(|_| {
+                        let _ = fs::remove_file(to);
+                    })
+                }
+                else {
+                    Err(err)
+                }
+            },
+        }
+    }
+    
+    /// Copy a file, keeping its modified time and permissions.
+    /// A failed or mismatched copy is removed, and nothing is overwritten.
+    pub fn copy_file(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let source = File::open(from)?;
+        let target = OpenOptions::new().write(true).create_new(true).open(to)?;
+        
+        // Only once there's a copy of ours to remove.
+        let result = self.copy_contents(source, target)
+            .and_then(|_| self.verify_copy(from, to))
+            .and_then(|_| copy_metadata(from, to));
+        
+        if result.is_err() {
+            let _ = fs::remove_file(to);
+        }
+        
+        result
+    }
+    
+    /// Copy the bytes of a file, reporting progress as it goes.
+    fn copy_contents(&self, mut source: File, mut target: File) -> io::Result<()> {
+        let total = source.metadata()?.len();
+        let mut copied = 0;
+        let mut buffer = vec![0; BUFFER_SIZE];
+        
+        loop {
+            let size = source.read(&mut buffer)?;
+            
+            if size == 0 {
+                break;
+            }
+            
+            target.write_all(&buffer[..size])?;
+            copied += size as u64;
+            (self.progress)(copied, total);
+        }
+        
+        target.sync_all()
+    }
+    
+    /// Check a copy matches the original.
+    fn verify_copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let matches = match self.verify {
+            Verify::Size => {
+                fs::metadata(from)?.len() == fs::metadata(to)?.len()
+            },
+            Verify::Contents => {
+                fs::metadata(from)?.len() == fs::metadata(to)?.len() &&
+                same_contents(from, to)?
+            },
+        };
+        
+        if matches {
+            Ok(())
+        }
+        else {
+            Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("Copy of {} doesn't match the original.", from.display())))
+        }
+    }
+}
+
+/// Whether an error is from renaming across file systems.
+fn is_cross_device(err: &io::Error) -> bool {
+    // EXDEV on unix, ERROR_NOT_SAME_DEVICE on windows.
+    #[cfg(unix)]
+    const CROSS_DEVICE: i32 = 18;
+    #[cfg(windows)]
+    const CROSS_DEVICE: i32 = 17;
+    
+    err.raw_os_error() == Some(CROSS_DEVICE)
+}
+
+/// Create a symlink at 'link' pointing to 'target'.
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Create a symlink at 'link' pointing to 'target'.
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// Whether a symlink still points at a file, by an absolute or relative path.
+pub fn links_to(link: &Path, target: &Path) -> bool {
+    let pointed = match fs::read_link(link) {
+        Ok(pointed) => link.parent().unwrap_or(Path::new(".")).join(pointed),
+        Err(_) => return false,
+    };
+    
+    match (pointed.canonicalize(), target.canonicalize()) {
+        (Ok(one), Ok(two)) => one == two,
+        _ => false,
+    }
+}
+
+/// Get the path to 'target' from the 'base' folder.
+/// Both must be absolute.
+fn relative_path(base: &Path, target: &Path) -> PathBuf {
+    let base: Vec<Component> = base.components().collect();
+    let target: Vec<Component> = target.components().collect();
+    
+    // Count the shared parent folders.
+    let common = base.iter()
+        .zip(target.iter())
+        .take_while(|(one, two)| one == two)
+        .count();
+    
+    let mut path = PathBuf::new();
+    
+    for _ in common..base.len() {
+        path.push("..");
+    }
+    
+    for component in &target[common..] {
+        path.push(component.as_os_str());
+    }
+    
+    path
+}
+
+/// Compare two files byte for byte.
+fn same_contents(one: &Path, two: &Path) -> io::Result<bool> {
+    let mut one = File::open(one)?;
+    let mut two = File::open(two)?;
+    
+    let mut one_buffer = vec![0; BUFFER_SIZE];
+    let mut two_buffer = vec![0; BUFFER_SIZE];
+    
+    loop {
+        let size = one.read(&mut one_buffer)?;
+        
+        if size == 0 {
+            // Both must be finished.
+            return Ok(two.read(&mut two_buffer)? == 0);
+        }
+        
+        // Reads can be short, so fill exactly the same amount.
+        if two.read_exact(&mut two_buffer[..size]).is_err() {
+            return Ok(false);
+        }
+        
+        if one_buffer[..size] != two_buffer[..size] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Copy the modified time and permissions of a file.
+fn copy_metadata(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(from)?;
+    
+    let times = FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+    
+    // Times first, read-only permissions would stop us writing them.
+    File::options().write(true).open(to)?.set_times(times)?;
+    fs::set_permissions(to, metadata.permissions())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+    
+    fn no_progress(_: u64, _: u64) {}
+    
+    fn create_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("tv-rename-transfer-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    #[test]
+    fn test_move() {
+        let dir = create_dir("move");
+        let from = dir.join("one.mp4");
+        let to = dir.join("two.mp4");
+        fs::write(&from, "episode").unwrap();
+        
+        let transfer = Transfer::new(Mode::Rename, Verify::Size, no_progress);
+        transfer.move_file(&from, &to).unwrap();
+        
+        assert!(!from.exists());
+        assert_eq!("episode", fs::read_to_string(&to).unwrap());
+    }
+    
+    #[test]
+    fn test_copy() {
+        let dir = create_dir("copy");
+        let from = dir.join("one.mp4");
+        let to = dir.join("two.mp4");
+        fs::write(&from, "episode").unwrap();
+        
+        // Make it old.
+        let modified = SystemTime::now() - Duration::from_secs(60 * 60 * 24);
+        File::options().write(true).open(&from).unwrap()
+            .set_modified(modified).unwrap();
+        
+        let transfer = Transfer::new(Mode::Copy, Verify::Contents, no_progress);
+        transfer.apply(&from, &to).unwrap();
+        
+        assert!(from.exists());
+        assert_eq!("episode", fs::read_to_string(&to).unwrap());
+        assert_eq!(modified, fs::metadata(&to).unwrap().modified().unwrap());
+    }
+    
+    #[test]
+    fn test_copy_missing() {
+        let dir = create_dir("copy-missing");
+        let from = dir.join("one.mp4");
+        let to = dir.join("two.mp4");
+        fs::write(&to, "someone else's episode").unwrap();
+        
+        let transfer = Transfer::new(Mode::Copy, Verify::Size, no_progress);
+        assert!(transfer.copy_file(&from, &to).is_err());
+        
+        // Not ours to remove.
+        assert_eq!("someone else's episode", fs::read_to_string(&to).unwrap());
+    }
+    
+    #[test]
+    fn test_copy_existing() {
+        let dir = create_dir("copy-existing");
+        let from = dir.join("one.mp4");
+        let to = dir.join("two.mp4");
+        fs::write(&from, "episode").unwrap();
+        fs::write(&to, "someone else's episode").unwrap();
+        
+        let transfer = Transfer::new(Mode::Copy, Verify::Size, no_progress);
+        let err = transfer.copy_file(&from, &to).unwrap_err();
+        
+        assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+        assert_eq!("someone else's episode", fs::read_to_string(&to).unwrap());
+    }
+    
+    #[test]
+    fn test_hardlink() {
+        let dir = create_dir("hardlink");
+        let from = dir.join("one.mp4");
+        let to = dir.join("two.mp4");
+        fs::write(&from, "episode").unwrap();
+        
+        let transfer = Transfer::new(Mode::Hardlink, Verify::Size, no_progress);
+        transfer.apply(&from, &to).unwrap();
+        
+        assert!(from.exists());
+        assert_eq!("episode", fs::read_to_string(&to).unwrap());
+    }
+    
+    #[test]
+    #[cfg(unix)]
+    fn test_symlinks() {
+        let dir = create_dir("symlink").canonicalize().unwrap();
+        let from = dir.join("downloads/one.mp4");
+        let absolute = dir.join("library/show/two.mp4");
+        let relative = dir.join("library/show/three.mp4");
+        fs::create_dir_all(from.parent().unwrap()).unwrap();
+        fs::create_dir_all(absolute.parent().unwrap()).unwrap();
+        fs::write(&from, "episode").unwrap();
+        
+        let transfer = Transfer::new(Mode::Symlink, Verify::Size, no_progress);
+        transfer.apply(&from, &absolute).unwrap();
+        
+        let transfer = Transfer::new(Mode::RelativeSymlink, Verify::Size, no_progress);
+        transfer.apply(&from, &relative).unwrap();
+        
+        assert_eq!(from, fs::read_link(&absolute).unwrap());
+        assert_eq!(PathBuf::from("../../downloads/one.mp4"), fs::read_link(&relative).unwrap());
+        assert_eq!("episode", fs::read_to_string(&relative).unwrap());
+        
+        assert!(links_to(&absolute, &from));
+        assert!(links_to(&relative, &from));
+        assert!(!links_to(&from, &from));
+    }
+    
+    #[test]
+    fn test_relative_path() {
+        let actual = relative_path(Path::new("/media/tv/show"), Path::new("/media/downloads/one.mp4"));
+        assert_eq!(PathBuf::from("../../downloads/one.mp4"), actual);
+        
+        let actual = relative_path(Path::new("/media"), Path::new("/media/one.mp4"));
+        assert_eq!(PathBuf::from("one.mp4"), actual);
+    }
+    
+    #[test]
+    fn test_mode_parse() {
+        assert_eq!(Some(Mode::Hardlink), Mode::parse("hardlink"));
+        assert_eq!(Some(Mode::RelativeSymlink), Mode::parse("relative-symlink"));
+        assert_eq!(None, Mode::parse("teleport"));
+    }
+    
+    #[test]
+    fn test_same_contents() {
+        let dir = create_dir("contents");
+        let one = dir.join("one.mp4");
+        let two = dir.join("two.mp4");
+        let three = dir.join("three.mp4");
+        fs::write(&one, "episode one").unwrap();
+        fs::write(&two, "episode one").unwrap();
+        fs::write(&three, "episode two").unwrap();
+        
+        assert!(same_contents(&one, &two).unwrap());
+        assert!(!same_contents(&one, &three).unwrap());
+    }
+    
+    #[test]
+    fn test_cross_device() {
+        #[cfg(unix)]
+        let err = io::Error::from_raw_os_error(18);
+        #[cfg(windows)]
+        let err = io::Error::from_raw_os_error(17);
+        
+        assert!(is_cross_device(&err));
+        assert!(!is_cross_device(&io::Error::from(io::ErrorKind::NotFound)));
+    }
+}